@@ -0,0 +1,16 @@
+/// How a range of a file is going to be accessed, passed to `RandomAccessFile::advise`. These are
+/// only hints: a backend is free to ignore them, and none of them change what reads return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Advice {
+    /// No particular pattern; undoes an earlier hint.
+    Normal,
+    /// The range will be read from start to end, so the kernel can read further ahead.
+    Sequential,
+    /// The range will be read in no particular order, so reading ahead is wasted.
+    Random,
+    /// The range will be needed soon and can be read in now.
+    WillNeed,
+    /// The range won't be needed again soon and its cached pages can be dropped, e.g. after a
+    /// scan that would otherwise evict everything else from the page cache.
+    DontNeed,
+}
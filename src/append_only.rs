@@ -0,0 +1,155 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
+use std::path::Path;
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+/// A wrapper around a `RandomAccessFile` under which data that has already been written can never
+/// be modified. `append` and `write_at` past the current end of the file behave as usual, but a
+/// `write_at` that would touch any byte before the end of the file returns an error of kind
+/// `PermissionDenied` without writing anything. Intended for audit logs, WALs and the like.
+#[derive(Debug)]
+pub struct AppendOnly<R: RandomAccessFile> {
+    inner: R,
+    len: u64,
+}
+
+impl<R: RandomAccessFile> AppendOnly<R> {
+    /// Wraps an already opened file. The current length of the file is the boundary below which
+    /// writes are rejected.
+    pub fn wrap(mut inner: R) -> Result<AppendOnly<R>, RafError> {
+        let len = inner.len()?;
+        Ok(AppendOnly { inner, len })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: OpenRandomAccessFile> OpenRandomAccessFile for AppendOnly<R> {
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<AppendOnly<R>, RafError> {
+        AppendOnly::wrap(R::open_with(path, options)?)
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for AppendOnly<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.inner.read_at(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        if at < self.len {
+            return Err(Error::new(ErrorKind::PermissionDenied,
+                                  "write_at into an already written region of an append only file").into());
+        }
+        let written = self.inner.write_at(at, data)?;
+        if written > 0 {
+            self.len = at + written as u64;
+        }
+        Ok(written)
+    }
+
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        self.inner.read_at_vectored(at, bufs)
+    }
+
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        if at < self.len {
+            return Err(Error::new(ErrorKind::PermissionDenied,
+                                  "write_at into an already written region of an append only file").into());
+        }
+        let written = self.inner.write_at_vectored(at, bufs)?;
+        if written > 0 {
+            self.len = at + written as u64;
+        }
+        Ok(written)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.inner.append(data)?;
+        self.len = at + data.len() as u64;
+        Ok(at)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    /// Only allowed past the end of the written data, since the bytes of a hole read back as zeros.
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        if at < self.len {
+            return Err(Error::new(ErrorKind::PermissionDenied,
+                                  "punch_hole into an already written region of an append only file").into());
+        }
+        self.inner.punch_hole(at, len)
+    }
+
+    /// The inner file's metadata, with `len` as reported by `len`.
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata { len: self.len, ..self.inner.metadata()? })
+    }
+
+    /// The number of bytes that have been written so far, and thus can no longer be modified.
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.len)
+    }
+
+    /// Only extending the file is allowed; truncating it would discard written data.
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        if len < self.len {
+            return Err(Error::new(ErrorKind::PermissionDenied, "cannot truncate an append only file").into());
+        }
+        self.inner.set_len(len)?;
+        self.len = len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use AppendOnly;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn rejects_overwrites() {
+        let path = env::temp_dir().join("raf_append_only.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: AppendOnly<File> = OpenRandomAccessFile::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(raf.append(b"hello").unwrap(), 0);
+        assert_eq!(raf.len().unwrap(), 5);
+        assert_eq!(raf.set_len(4).unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(raf.write_at(3, b"!!").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(raf.write_at(5, b" world").unwrap(), 6);
+        assert_eq!(raf.write_at(10, b"d").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(raf.punch_hole(8, 100).unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        let mut buf = [0u8; 11];
+        raf.read_at(0, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello world");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::future::Future;
+use std::io::Error;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::thread;
+use RafError;
+use RandomAccessFile;
+
+/// The future returned by `AsyncRandomAccessFile` methods. It owns everything it needs, so it can
+/// be moved to and awaited on any task.
+pub type RafFuture<T> = Pin<Box<dyn Future<Output = Result<T, RafError>> + Send>>;
+
+/// The asynchronous counterpart of `RandomAccessFile`. Buffers are passed and returned by value,
+/// since a borrowed buffer could be dropped while the operation is still in flight.
+///
+/// The futures are plain `std::future::Future`s that rely on nothing but their `Waker`, so they
+/// can be awaited under tokio, async-std, smol or any other executor.
+pub trait AsyncRandomAccessFile {
+    /// Reads up to `len` bytes at `at`; like `RandomAccessFile::read_at` the result may be
+    /// shorter.
+    fn read_at(&self, at: u64, len: usize) -> RafFuture<Vec<u8>>;
+    /// Reads exactly `len` bytes at `at`, failing with `RafError::ShortRead` at the end of the
+    /// file.
+    fn read_exact_at(&self, at: u64, len: usize) -> RafFuture<Vec<u8>>;
+    /// Writes some prefix of `data` at `at`, returning its length.
+    fn write_at(&self, at: u64, data: Vec<u8>) -> RafFuture<usize>;
+    /// Writes all of `data` at `at`.
+    fn write_all_at(&self, at: u64, data: Vec<u8>) -> RafFuture<()>;
+    /// Writes `data` at the end of the file, returning the offset it was written at.
+    fn append(&self, data: Vec<u8>) -> RafFuture<u64>;
+    fn len(&self) -> RafFuture<u64>;
+    fn is_empty(&self) -> RafFuture<bool>;
+    fn set_len(&self, len: u64) -> RafFuture<()>;
+    fn flush(&self) -> RafFuture<()>;
+    fn sync_data(&self) -> RafFuture<()>;
+    fn sync_all(&self) -> RafFuture<()>;
+}
+
+struct Slot<T> {
+    result: Option<Result<T, RafError>>,
+    waker: Option<Waker>,
+}
+
+// The receiving half of an operation's result.
+struct Pending<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Pending<T> {
+    type Output = Result<T, RafError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, RafError>> {
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// The sending half. If it is dropped without a result, because the operation panicked or never
+// ran, the future fails instead of waiting forever.
+struct Completer<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Completer<T> {
+    fn complete(&self, result: Result<T, RafError>) {
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.result.is_none() {
+            slot.result = Some(result);
+        }
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.complete(Err(Error::other("the operation was abandoned").into()));
+    }
+}
+
+type Job<R> = Box<dyn FnOnce(&mut R) + Send>;
+
+/// A function that runs a blocking job somewhere off the executor's threads, like tokio's or
+/// async-std's `spawn_blocking`, or smol's `unblock`.
+pub type Spawner = Arc<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+enum Runner<R> {
+    Worker(Sender<Job<R>>),
+    Spawned(Arc<Mutex<R>>, Spawner),
+}
+
+/// An `AsyncRandomAccessFile` that runs the operations of a blocking `RandomAccessFile` off the
+/// executor's threads, so async code can use any backend without blocking its executor.
+///
+/// Created with `new`, it has a worker thread of its own. Operations run one at a time, in the
+/// order they were started. The worker thread exits, and drops the inner file, once the handle is
+/// dropped and the operations already started are done. If an operation panics the worker thread
+/// stops, and that operation and every later one fails.
+///
+/// Created with `with_spawner`, each operation is handed to the runtime's own blocking thread
+/// pool instead, which spares a thread per handle and ties the handle to no particular runtime.
+/// Operations still run one at a time, but ones started without waiting for each other may run
+/// in any order. After an operation panics every later one fails.
+pub struct AsyncRaf<R: RandomAccessFile + Send + 'static> {
+    runner: Runner<R>,
+}
+
+impl<R: RandomAccessFile + Send + 'static> fmt::Debug for AsyncRaf<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let runner = match self.runner {
+            Runner::Worker(_) => "worker thread",
+            Runner::Spawned(..) => "spawner",
+        };
+        f.debug_struct("AsyncRaf").field("runner", &runner).finish()
+    }
+}
+
+impl<R: RandomAccessFile + Send + 'static> AsyncRaf<R> {
+    /// Starts a worker thread for `inner`.
+    pub fn new(inner: R) -> Result<AsyncRaf<R>, RafError> {
+        let (jobs, queue) = mpsc::channel::<Job<R>>();
+        thread::Builder::new().name("raf-async".to_string()).spawn(move || {
+            let mut inner = inner;
+            for job in queue {
+                job(&mut inner);
+            }
+        })?;
+        Ok(AsyncRaf { runner: Runner::Worker(jobs) })
+    }
+
+    /// Runs every operation on `inner` through `spawn`, for example
+    /// `Arc::new(|job| { async_std::task::spawn_blocking(job); })`.
+    pub fn with_spawner(inner: R, spawn: Spawner) -> AsyncRaf<R> {
+        AsyncRaf { runner: Runner::Spawned(Arc::new(Mutex::new(inner)), spawn) }
+    }
+
+    /// Runs `op` against the inner file on the worker thread. This is how operations that
+    /// `AsyncRandomAccessFile` doesn't cover can be performed.
+    pub fn run<T, F>(&self, op: F) -> RafFuture<T>
+        where T: Send + 'static, F: FnOnce(&mut R) -> Result<T, RafError> + Send + 'static {
+        let slot = Arc::new(Mutex::new(Slot { result: None, waker: None }));
+        let completer = Completer { slot: slot.clone() };
+        // If the job can't run, because the worker is gone or the file was poisoned by a panic,
+        // the completer is dropped, failing the future.
+        match self.runner {
+            Runner::Worker(ref jobs) => {
+                let _ = jobs.send(Box::new(move |inner: &mut R| completer.complete(op(inner))));
+            },
+            Runner::Spawned(ref inner, ref spawn) => {
+                let inner = inner.clone();
+                spawn(Box::new(move || {
+                    if let Ok(mut inner) = inner.lock() {
+                        completer.complete(op(&mut inner));
+                    }
+                }));
+            }
+        }
+        Box::pin(Pending { slot })
+    }
+}
+
+impl<R: RandomAccessFile + Send + 'static> AsyncRandomAccessFile for AsyncRaf<R> {
+    fn read_at(&self, at: u64, len: usize) -> RafFuture<Vec<u8>> {
+        self.run(move |inner| {
+            let mut buf = vec![0u8; len];
+            let n = inner.read_at(at, &mut buf)?;
+            buf.truncate(n);
+            Ok(buf)
+        })
+    }
+
+    fn read_exact_at(&self, at: u64, len: usize) -> RafFuture<Vec<u8>> {
+        self.run(move |inner| {
+            let mut buf = vec![0u8; len];
+            inner.read_exact_at(at, &mut buf)?;
+            Ok(buf)
+        })
+    }
+
+    fn write_at(&self, at: u64, data: Vec<u8>) -> RafFuture<usize> {
+        self.run(move |inner| inner.write_at(at, &data))
+    }
+
+    fn write_all_at(&self, at: u64, data: Vec<u8>) -> RafFuture<()> {
+        self.run(move |inner| inner.write_all_at(at, &data))
+    }
+
+    fn append(&self, data: Vec<u8>) -> RafFuture<u64> {
+        self.run(move |inner| inner.append(&data))
+    }
+
+    fn len(&self) -> RafFuture<u64> {
+        self.run(|inner| inner.len())
+    }
+
+    fn is_empty(&self) -> RafFuture<bool> {
+        self.run(|inner| inner.is_empty())
+    }
+
+    fn set_len(&self, len: u64) -> RafFuture<()> {
+        self.run(move |inner| inner.set_len(len))
+    }
+
+    fn flush(&self) -> RafFuture<()> {
+        self.run(|inner| inner.flush())
+    }
+
+    fn sync_data(&self) -> RafFuture<()> {
+        self.run(|inner| inner.sync_data())
+    }
+
+    fn sync_all(&self) -> RafFuture<()> {
+        self.run(|inner| inner.sync_all())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_raf::{AsyncRaf, AsyncRandomAccessFile, Spawner};
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread;
+    use std::thread::Thread;
+    use MemoryRandomAccessFile;
+    use RafError;
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // A minimal executor, standing in for whichever runtime the caller uses.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Arc::new(Unpark(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park()
+            }
+        }
+    }
+
+    #[test]
+    fn operations_complete_in_order() {
+        let raf = AsyncRaf::new(MemoryRandomAccessFile::new()).unwrap();
+        let first = raf.append(b"async ".to_vec());
+        let second = raf.append(b"file".to_vec());
+        let read = raf.read_at(4, 100);
+        assert_eq!(block_on(read).unwrap(), b"c file");
+        assert_eq!((block_on(first).unwrap(), block_on(second).unwrap()), (0, 6));
+
+        block_on(raf.write_all_at(0, b"A".to_vec())).unwrap();
+        assert_eq!(block_on(raf.len()).unwrap(), 10);
+        assert_eq!(block_on(raf.read_exact_at(0, 5)).unwrap(), b"Async");
+        match block_on(raf.read_exact_at(8, 4)) {
+            Err(RafError::ShortRead { offset: 8, expected: 4, read: 2 }) => (),
+            other => panic!("{:?}", other)
+        }
+        let err = block_on(raf.run(|_| -> Result<(), RafError> { panic!("boom") })).unwrap_err();
+        assert_eq!(err.to_string(), "the operation was abandoned");
+        assert!(block_on(raf.len()).is_err());
+    }
+
+    #[test]
+    fn runs_on_a_spawner() {
+        let spawn: Spawner = Arc::new(|job| { thread::spawn(job); });
+        let raf = AsyncRaf::with_spawner(MemoryRandomAccessFile::new(), spawn);
+        assert_eq!(block_on(raf.append(b"spawned".to_vec())).unwrap(), 0);
+        assert_eq!(block_on(raf.read_at(5, 10)).unwrap(), b"ed");
+        assert!(!block_on(raf.is_empty()).unwrap());
+        let _ = block_on(raf.run(|_| -> Result<(), RafError> { panic!("boom") }));
+        assert!(block_on(raf.len()).is_err());
+    }
+}
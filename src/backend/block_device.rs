@@ -0,0 +1,193 @@
+//! `BlockDeviceRandomAccessFile`, a disk or partition opened directly.
+
+#[cfg(target_os = "linux")]
+use libc;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Seek;
+use std::io::SeekFrom;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+const DEFAULT_SECTOR_SIZE: usize = 512;
+
+// The logical sector size the kernel reports for a block device, or None for anything else.
+#[cfg(target_os = "linux")]
+fn logical_sector_size(file: &File) -> Option<usize> {
+    let mut size: libc::c_int = 0;
+    match unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKSSZGET, &mut size as *mut libc::c_int) } {
+        0 if size > 0 => Some(size as usize),
+        _ => None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn logical_sector_size(_file: &File) -> Option<usize> {
+    None
+}
+
+/// A `RandomAccessFile` for a block device such as `/dev/sdb` or one of its partitions, for
+/// programs, like file systems, that manage the raw device themselves. Every read and write must
+/// start on a sector boundary and cover whole sectors, or it fails with `ErrorKind::InvalidInput`;
+/// so must writes that would run past the end of the device. The device can't change size, so
+/// `append` and `set_len` fail with `ErrorKind::Unsupported`.
+///
+/// The sector size is the device's logical sector size on Linux and 512 bytes elsewhere, or for a
+/// disk image in a regular file. The size of the device is read once, when it is opened.
+#[derive(Debug)]
+pub struct BlockDeviceRandomAccessFile {
+    file: File,
+    sector_size: usize,
+    size: u64,
+}
+
+impl BlockDeviceRandomAccessFile {
+    /// Wraps an opened device, asking it for its sector size and size.
+    pub fn from_file(file: File) -> Result<BlockDeviceRandomAccessFile, RafError> {
+        let sector_size = logical_sector_size(&file).unwrap_or(DEFAULT_SECTOR_SIZE);
+        BlockDeviceRandomAccessFile::with_sector_size(file, sector_size)
+    }
+
+    /// Wraps an opened device or disk image with the given sector size. Panics if `sector_size`
+    /// is not a power of two.
+    pub fn with_sector_size(mut file: File, sector_size: usize) -> Result<BlockDeviceRandomAccessFile, RafError> {
+        assert!(sector_size.is_power_of_two(), "the sector size must be a power of two");
+        // The metadata of a block device has a length of 0, but seeking to the end finds it.
+        let size = file.seek(SeekFrom::End(0))?;
+        Ok(BlockDeviceRandomAccessFile { file, sector_size, size })
+    }
+
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    /// The size of the device in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The number of whole sectors on the device.
+    pub fn sectors(&self) -> u64 {
+        self.size / self.sector_size as u64
+    }
+
+    fn check_aligned(&self, at: u64, len: usize) -> Result<(), RafError> {
+        let mask = self.sector_size - 1;
+        if at as usize & mask != 0 || len & mask != 0 {
+            let message = format!("{} bytes at {} is not a whole number of {} byte sectors", len, at, self.sector_size);
+            return Err(Error::new(ErrorKind::InvalidInput, message).into());
+        }
+        Ok(())
+    }
+}
+
+fn cannot_resize() -> RafError {
+    Error::new(ErrorKind::Unsupported, "a block device can't change size").into()
+}
+
+impl OpenRandomAccessFile for BlockDeviceRandomAccessFile {
+    /// Opens the device with `create`, `create_new` and `truncate` unset, whatever `options` says,
+    /// so that a mistyped device path fails with `NotFound` instead of creating an empty file.
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<BlockDeviceRandomAccessFile, RafError> {
+        let path = path.as_ref();
+        let file = options.clone().create(false).create_new(false).truncate(false).open::<File, _>(path)?;
+        BlockDeviceRandomAccessFile::from_file(file).map_err(|e| e.with_path(path))
+    }
+}
+
+impl RandomAccessFile for BlockDeviceRandomAccessFile {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.check_aligned(at, dat.len())?;
+        self.file.read_at(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.check_aligned(at, data.len())?;
+        if at.checked_add(data.len() as u64).is_none_or(|end| end > self.size) {
+            return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the device").into());
+        }
+        self.file.write_at(at, data)
+    }
+
+    fn append(&mut self, _data: &[u8]) -> Result<u64, RafError> {
+        Err(cannot_resize())
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.size)
+    }
+
+    fn set_len(&mut self, _len: u64) -> Result<(), RafError> {
+        Err(cannot_resize())
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        let mut metadata = RafMetadata::from(File::metadata(&self.file)?);
+        metadata.len = self.size;
+        Ok(metadata)
+    }
+
+    fn try_clone(&self) -> Result<BlockDeviceRandomAccessFile, RafError> {
+        Ok(BlockDeviceRandomAccessFile { file: self.file.try_clone()?, sector_size: self.sector_size, size: self.size })
+    }
+
+    /// Does nothing: writes are not buffered by this handle.
+    fn flush(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.file.sync_data().map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.file.sync_all().map_err(RafError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::block_device::BlockDeviceRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RafOptions;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn sector_aligned_access() {
+        // A disk image stands in for a device.
+        let path = env::temp_dir().join("raf_block_device.img");
+        fs::write(&path, vec![0u8; 4096]).unwrap();
+        let mut raf: BlockDeviceRandomAccessFile = RafOptions::new().open(&path).unwrap();
+        assert_eq!((raf.sector_size(), raf.size(), raf.sectors()), (512, 4096, 8));
+        assert_eq!(raf.metadata().unwrap().len, 4096);
+
+        let sector = [7u8; 512];
+        raf.write_all_at(1024, &sector).unwrap();
+        let mut buf = [0u8; 1024];
+        raf.read_exact_at(512, &mut buf).unwrap();
+        assert_eq!(&buf[512..], &sector[..]);
+
+        assert_eq!(raf.read_at(100, &mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(raf.write_at(512, &sector[..100]).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(raf.write_at(4096, &sector).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(raf.append(&sector).unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(raf.set_len(0).unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(raf.try_clone().unwrap().len().unwrap(), 4096);
+        let _ = fs::remove_file(&path);
+
+        // `new` sets `create`, which a device doesn't get.
+        let err = <BlockDeviceRandomAccessFile as OpenRandomAccessFile>::new(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!path.exists());
+    }
+}
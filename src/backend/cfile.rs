@@ -0,0 +1,131 @@
+//! `RandomAccessFile` for `cfile_rs::CFile`, a wrapper around libc's `FILE*`.
+
+use cfile_rs::CFile;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+impl OpenRandomAccessFile for CFile {
+    // fopen's modes can't express create_new or permissions, so the file is created or truncated
+    // through std first and then reopened with stdio.
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<CFile, RafError> {
+        let path = utf8_path(path.as_ref())?;
+        let opened = if options.is_read_only() {
+            CFile::open(path, "r")
+        } else {
+            options.to_open_options().open(path).and_then(|_| CFile::open_random_access(path))
+        };
+        opened.map_err(|e| RafError::from(e).with_path(path))
+    }
+}
+
+impl RandomAccessFile for CFile {
+    // A failed seek must not fall through to reading or writing at wherever the stream happens
+    // to be. The seek also flushes stdio's buffer, which C requires between writes and reads.
+    // `CFile` has nowhere to remember the cursor, so every call seeks; `SeekableRaf<CFile>` keeps
+    // track of it and skips the redundant ones.
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.seek(SeekFrom::Start(at))?;
+        self.read(dat).map_err(RafError::from)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.seek(SeekFrom::Start(at))?;
+        self.write(data).map_err(RafError::from)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.seek(SeekFrom::End(0))?;
+        self.write_all(data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        let pos = self.current_pos()?;
+        let len = self.seek(SeekFrom::End(0))?;
+        self.seek(SeekFrom::Start(pos))?;
+        Ok(len)
+    }
+
+    // stdio has no truncate, so buffered data is flushed and the file is truncated through a
+    // second, std handle on the same path.
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        second_handle(self)?.set_len(len).map_err(RafError::from)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata::from(second_handle(self)?.metadata()?))
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        Write::flush(self).map_err(RafError::from)
+    }
+
+    // Likewise stdio has no fsync. Syncing any descriptor of a file syncs the file itself, so the
+    // second handle is used for this too.
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        second_handle(self)?.sync_data().map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        second_handle(self)?.sync_all().map_err(RafError::from)
+    }
+}
+
+/// `CFile` only accepts UTF-8 paths.
+pub(crate) fn utf8_path(path: &Path) -> Result<&str, Error> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => Err(Error::new(ErrorKind::InvalidInput, "CFile paths must be valid UTF-8"))
+    }
+}
+
+/// Flushes stdio's buffer and opens a std handle on the same path.
+fn second_handle(file: &mut CFile) -> Result<File, Error> {
+    Write::flush(file)?;
+    let path = match file.path.to_str() {
+        Ok(path) => path.to_owned(),
+        Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "file path is not valid UTF-8"))
+    };
+    OpenOptions::new().write(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use cfile_rs::CFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use Serialize;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+
+    #[test]
+    fn it_works_cfile() {
+        let mut raf: CFile = OpenRandomAccessFile::new("test_cfile.txt").unwrap();
+        65u64.serialize(&mut raf).unwrap();
+        raf.seek(SeekFrom::Start(0)).unwrap();
+        let t = u64::deserialize(&mut raf).unwrap();
+        assert!(t == 65)
+    }
+
+    #[test]
+    fn seek_errors_are_reported() {
+        let mut raf: CFile = OpenRandomAccessFile::new("test_cfile_seek.txt").unwrap();
+        raf.write_all_at(0, b"abc").unwrap();
+        assert!(raf.read_at(u64::MAX, &mut [0u8; 2]).is_err());
+        assert!(raf.write_at(u64::MAX, b"x").is_err());
+        assert_eq!(raf.len().unwrap(), 3);
+        let _ = ::std::fs::remove_file("test_cfile_seek.txt");
+    }
+}
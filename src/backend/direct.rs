@@ -0,0 +1,304 @@
+//! `DirectRandomAccessFile`, a `File` opened for direct I/O.
+
+use std::alloc;
+use std::alloc::Layout;
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::slice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+const DEFAULT_ALIGNMENT: usize = 4096;
+// The number of buffers kept for reuse; any more are freed when returned.
+const POOLED_BUFFERS: usize = 4;
+
+// A zero filled heap buffer whose address is a multiple of its alignment.
+struct AlignedBuf {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// The buffer owns its memory like a Vec<u8> does.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    fn new(size: usize, alignment: usize) -> AlignedBuf {
+        let layout = Layout::from_size_align(size, alignment).expect("buffer too large");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuf { ptr, layout }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AlignedBuf({} bytes)", self.layout.size())
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// A `RandomAccessFile` for a `File` opened with `RafOptions::direct`, which bypasses the page
+/// cache. Direct I/O only accepts transfers whose offset, length and buffer address are all
+/// multiples of the device's block size; `read_at` and `write_at` still accept any offset and
+/// slice, and go through a small pool of aligned buffers when they are not aligned themselves.
+/// An unaligned write reads the blocks at either end before writing them back, so it costs up to
+/// two extra reads. That read-modify-write takes no lock: two handles to the same file, clones
+/// included, that write into the same block at the same time can undo each other's writes, so
+/// they must write to separate blocks.
+///
+/// The alignment defaults to 4096 bytes, which suits practically every device. Only Linux opens
+/// the file with `O_DIRECT`; elsewhere the same aligned transfers go through the page cache. The
+/// file is never exposed through `as_file`, since unaligned transfers on it would fail.
+#[derive(Debug)]
+pub struct DirectRandomAccessFile {
+    file: File,
+    alignment: usize,
+    pool: Vec<AlignedBuf>,
+}
+
+impl DirectRandomAccessFile {
+    /// Wraps a file opened with `RafOptions::direct`, making every transfer a multiple of
+    /// `alignment` bytes. Panics if `alignment` is not a power of two.
+    pub fn from_file(file: File, alignment: usize) -> DirectRandomAccessFile {
+        assert!(alignment.is_power_of_two(), "the alignment must be a power of two");
+        DirectRandomAccessFile { file, alignment, pool: Vec::new() }
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    fn is_aligned(&self, at: u64, buf: &[u8]) -> bool {
+        let mask = self.alignment - 1;
+        at as usize & mask == 0 && buf.len() & mask == 0 && buf.as_ptr() as usize & mask == 0
+    }
+
+    // The aligned range covering `len` bytes at `at`.
+    fn aligned_range(&self, at: u64, len: usize) -> Result<(u64, u64), RafError> {
+        let mask = self.alignment as u64 - 1;
+        match at.checked_add(len as u64).and_then(|end| end.checked_add(mask)) {
+            Some(end) => Ok((at & !mask, end & !mask)),
+            None => Err(Error::new(ErrorKind::InvalidInput, "transfer past the end of the address space").into())
+        }
+    }
+
+    // A zeroed buffer of at least `size` bytes, from the pool if one there is large enough.
+    fn take_buf(&mut self, size: usize) -> AlignedBuf {
+        match self.pool.iter().position(|buf| buf.len() >= size) {
+            Some(i) => {
+                let mut buf = self.pool.swap_remove(i);
+                for b in buf.iter_mut() {
+                    *b = 0;
+                }
+                buf
+            },
+            None => AlignedBuf::new(size, self.alignment)
+        }
+    }
+
+    fn return_buf(&mut self, buf: AlignedBuf) {
+        if self.pool.len() < POOLED_BUFFERS {
+            self.pool.push(buf);
+        }
+    }
+
+    // Reads the block at `at` into `block`, leaving the part past the end of the file zeroed.
+    fn read_block(&mut self, at: u64, block: &mut [u8]) -> Result<(), RafError> {
+        let mut filled = 0;
+        while filled < block.len() {
+            match self.file.read_at(at + filled as u64, &mut block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    fn write_through(&mut self, at: u64, data: &[u8], buf: &mut [u8]) -> Result<(), RafError> {
+        let len = File::metadata(&self.file)?.len();
+        let (start, end) = self.aligned_range(at, data.len())?;
+        let alignment = self.alignment;
+        if at > start && start < len {
+            self.read_block(start, &mut buf[..alignment])?;
+        }
+        let data_end = at + data.len() as u64;
+        let last = end - alignment as u64;
+        if data_end < end && last < len && (last > start || at == start) {
+            let offset = (last - start) as usize;
+            self.read_block(last, &mut buf[offset..offset + alignment])?;
+        }
+        let offset = (at - start) as usize;
+        buf[offset..offset + data.len()].copy_from_slice(data);
+        self.file.write_all_at(start, &buf[..(end - start) as usize])?;
+        // The padding of the last block must not lengthen the file.
+        if end > len {
+            self.file.set_len(cmp::max(len, data_end))?;
+        }
+        Ok(())
+    }
+}
+
+impl OpenRandomAccessFile for DirectRandomAccessFile {
+    /// Opens the file with `direct` set, whatever `options` says, and an alignment of 4096 bytes.
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<DirectRandomAccessFile, RafError> {
+        let file = options.clone().direct(true).open::<File, _>(path)?;
+        Ok(DirectRandomAccessFile::from_file(file, DEFAULT_ALIGNMENT))
+    }
+}
+
+impl RandomAccessFile for DirectRandomAccessFile {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        if dat.is_empty() {
+            return Ok(0);
+        }
+        if self.is_aligned(at, dat) {
+            return self.file.read_at(at, dat);
+        }
+        let (start, end) = self.aligned_range(at, dat.len())?;
+        let mut buf = self.take_buf((end - start) as usize);
+        let result = self.file.read_at(start, &mut buf[..(end - start) as usize]);
+        let offset = (at - start) as usize;
+        let n = result.map(|read| {
+            let n = cmp::min(read.saturating_sub(offset), dat.len());
+            dat[..n].copy_from_slice(&buf[offset..offset + n]);
+            n
+        });
+        self.return_buf(buf);
+        n
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        if self.is_aligned(at, data) {
+            return self.file.write_at(at, data);
+        }
+        let (start, end) = self.aligned_range(at, data.len())?;
+        let mut buf = self.take_buf((end - start) as usize);
+        let result = self.write_through(at, data, &mut buf);
+        self.return_buf(buf);
+        result.map(|()| data.len())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.len()?;
+        self.write_all_at(at, data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(File::metadata(&self.file)?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.file.set_len(len).map_err(RafError::from)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata::from(File::metadata(&self.file)?))
+    }
+
+    /// The clone must not write into blocks this handle is writing to; see the type's documentation.
+    fn try_clone(&self) -> Result<DirectRandomAccessFile, RafError> {
+        Ok(DirectRandomAccessFile::from_file(self.file.try_clone()?, self.alignment))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        ::backend::std_file::allocate(&self.file, len)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        ::backend::std_file::punch_hole(&self.file, at, len)
+    }
+
+    /// Does nothing: writes are not buffered by this handle or the page cache.
+    fn flush(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.file.sync_data().map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.file.sync_all().map_err(RafError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::direct::DirectRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn unaligned_transfers() {
+        let path = env::temp_dir().join("raf_direct.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: DirectRandomAccessFile = OpenRandomAccessFile::new(&path).unwrap();
+        assert_eq!(raf.alignment(), 4096);
+
+        assert_eq!(raf.append(b"direct").unwrap(), 0);
+        assert_eq!(raf.len().unwrap(), 6);
+        let spanning: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        raf.write_all_at(4000, &spanning).unwrap();
+        raf.write_all_at(2, b"RE").unwrap();
+        assert_eq!(raf.len().unwrap(), 9000);
+
+        let mut buf = vec![0u8; 5000];
+        raf.read_exact_at(4000, &mut buf).unwrap();
+        assert_eq!(buf, spanning);
+        assert_eq!(raf.read_at(8990, &mut buf).unwrap(), 10);
+        assert_eq!(raf.read_at(9000, &mut buf).unwrap(), 0);
+
+        let mut clone = raf.try_clone().unwrap();
+        clone.set_len(8192).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(&contents[..8], b"diREct\0\0");
+        assert_eq!(&contents[4000..], &spanning[..4192]);
+
+        // A transfer that is already aligned, like this pooled buffer, skips the copy.
+        let mut block = raf.take_buf(4096);
+        raf.read_exact_at(4096, &mut block).unwrap();
+        assert_eq!(&block[..], &contents[4096..]);
+        raf.write_all_at(0, &block).unwrap();
+        assert_eq!(&fs::read(&path).unwrap()[..4096], &contents[4096..]);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,205 @@
+use std::cmp;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+use StorageKind;
+
+/// A `RandomAccessFile` backed by a growable `Vec<u8>`, for tests and for building files in memory.
+/// It follows the semantics of a real file: reads past the end return 0 bytes, and writing past
+/// the end grows the buffer, filling any gap with zeros.
+///
+/// Like `File` it also implements `Read`, `Write` and `Seek` around its own cursor, so values can
+/// be serialized into it directly. `read_at`/`write_at` don't move that cursor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryRandomAccessFile {
+    data: Vec<u8>,
+    pos: u64,
+}
+
+impl MemoryRandomAccessFile {
+    pub fn new() -> MemoryRandomAccessFile {
+        MemoryRandomAccessFile::default()
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> MemoryRandomAccessFile {
+        MemoryRandomAccessFile { data, pos: 0 }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+}
+
+impl RandomAccessFile for MemoryRandomAccessFile {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        if at >= self.data.len() as u64 {
+            return Ok(0);
+        }
+        let at = at as usize;
+        let n = cmp::min(dat.len(), self.data.len() - at);
+        dat[..n].copy_from_slice(&self.data[at..at + n]);
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let end = match at.checked_add(data.len() as u64) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the address space").into())
+        };
+        let at = at as usize;
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        self.data[at..end].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.data.len() as u64;
+        self.data.extend_from_slice(data);
+        Ok(at)
+    }
+
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        let at = self.data.len() as u64;
+        for buf in bufs {
+            self.data.extend_from_slice(buf);
+        }
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.data.len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        if len > usize::MAX as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput, "length does not fit in memory").into());
+        }
+        self.data.resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata { len: self.data.len() as u64, modified: None, permissions: None, kind: StorageKind::Memory })
+    }
+
+    /// There is nothing to deallocate, so the range is only zeroed; the part past the end of the
+    /// buffer is ignored.
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        let size = self.data.len() as u64;
+        let start = cmp::min(at, size) as usize;
+        let end = cmp::min(at.saturating_add(len), size) as usize;
+        for b in &mut self.data[start..end] {
+            *b = 0;
+        }
+        Ok(())
+    }
+
+    /// Needs no lock: the buffer can't be reached through any other handle.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        ::lock::compare_and_write(self, at, expected, new)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+
+    // There's no storage to sync to; the data only lives as long as the process.
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+}
+
+impl Read for MemoryRandomAccessFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let pos = self.pos;
+        let n = self.read_at(pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for MemoryRandomAccessFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let pos = self.pos;
+        let n = self.write_at(pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryRandomAccessFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => (self.data.len() as u64).checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match target {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            },
+            None => Err(Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::memory::MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use Serialize;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+
+    #[test]
+    fn write_past_end_zero_fills() {
+        let mut raf = MemoryRandomAccessFile::new();
+        assert_eq!(raf.write_at(3, b"abc").unwrap(), 3);
+        assert_eq!(raf.append(b"d").unwrap(), 6);
+        assert_eq!(raf.as_slice(), b"\0\0\0abcd");
+
+        let mut buf = [0xFFu8; 4];
+        assert_eq!(raf.read_at(5, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"cd");
+        assert_eq!(raf.read_at(100, &mut buf).unwrap(), 0);
+
+        raf.punch_hole(4, 10).unwrap();
+        assert_eq!(raf.as_slice(), b"\0\0\0a\0\0\0");
+
+        raf.set_len(2).unwrap();
+        assert_eq!(raf.len().unwrap(), 2);
+        assert!(raf.compare_and_write_at(0, b"\0\0", b"ok").unwrap());
+        assert!(!raf.compare_and_write_at(0, b"\0\0", b"no").unwrap());
+    }
+
+    #[test]
+    fn serialize_through_cursor() {
+        let mut raf = MemoryRandomAccessFile::new();
+        String::from("in memory").serialize(&mut raf).unwrap();
+        raf.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(String::deserialize(&mut raf).unwrap(), "in memory");
+    }
+}
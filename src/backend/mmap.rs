@@ -0,0 +1,219 @@
+#[cfg(unix)]
+use memmap2;
+use memmap2::MmapMut;
+use std::cmp;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+#[cfg(unix)]
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+/// A `RandomAccessFile` that memory maps the whole file, so `read_at` and `write_at` are plain
+/// copies into and out of the mapping. Writes past the end grow the file and remap it.
+///
+/// Writes reach the file through the page cache; call `flush` to have them written back to disk.
+/// As with any memory map, the behaviour is undefined if another process truncates the file while
+/// it is mapped.
+#[derive(Debug)]
+pub struct MmapRandomAccessFile {
+    file: File,
+    // None while the file is empty, since empty files cannot be mapped.
+    map: Option<MmapMut>,
+}
+
+impl MmapRandomAccessFile {
+    /// Maps an already opened file, which must have been opened for reading and writing.
+    pub fn from_file(file: File) -> Result<MmapRandomAccessFile, Error> {
+        let mut raf = MmapRandomAccessFile { file, map: None };
+        raf.remap()?;
+        Ok(raf)
+    }
+
+    fn remap(&mut self) -> Result<(), Error> {
+        self.map = None;
+        if self.file.metadata()?.len() > 0 {
+            self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        }
+        Ok(())
+    }
+
+    fn mapped_len(&self) -> usize {
+        self.map.as_ref().map_or(0, |map| map.len())
+    }
+}
+
+impl OpenRandomAccessFile for MmapRandomAccessFile {
+    /// Read only options are rejected with `Unsupported`, since the mapping is always writable.
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<MmapRandomAccessFile, RafError> {
+        if options.is_read_only() {
+            return Err(Error::new(ErrorKind::Unsupported, "MmapRandomAccessFile cannot be opened read only").into());
+        }
+        let path = path.as_ref();
+        MmapRandomAccessFile::from_file(options.open(path)?).map_err(|e| RafError::from(e).with_path(path))
+    }
+}
+
+impl RandomAccessFile for MmapRandomAccessFile {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        match self.map {
+            Some(ref map) if at < map.len() as u64 => {
+                let at = at as usize;
+                let n = cmp::min(dat.len(), map.len() - at);
+                dat[..n].copy_from_slice(&map[at..at + n]);
+                Ok(n)
+            },
+            _ => Ok(0)
+        }
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let end = match at.checked_add(data.len() as u64) {
+            Some(end) if end <= usize::MAX as u64 => end as usize,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the address space").into())
+        };
+        if end > self.mapped_len() {
+            self.set_len(end as u64)?;
+        }
+        if let Some(ref mut map) = self.map {
+            map[at as usize..end].copy_from_slice(data);
+        }
+        Ok(data.len())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.mapped_len() as u64;
+        self.write_at(at, data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.mapped_len() as u64)
+    }
+
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.file.lock()?;
+        let result = ::lock::compare_and_write(self, at, expected, new);
+        self.file.unlock()?;
+        result
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        let metadata = RafMetadata::from(self.file.metadata()?);
+        Ok(RafMetadata { len: self.mapped_len() as u64, ..metadata })
+    }
+
+    /// Duplicates the file descriptor and maps the file again. Both mappings are shared, so writes
+    /// through either are seen by the other, but each only covers the length the file had when it
+    /// was mapped: after one handle grows the file, the other keeps reporting the old length, and
+    /// reads nothing past it, until it remaps because of a `set_len` or a write past its end.
+    fn try_clone(&self) -> Result<MmapRandomAccessFile, RafError> {
+        MmapRandomAccessFile::from_file(self.file.try_clone()?).map_err(RafError::from)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.flush()?;
+        self.map = None;
+        self.file.set_len(len)?;
+        self.remap().map_err(RafError::from)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        if len <= self.mapped_len() as u64 {
+            return Ok(());
+        }
+        self.flush()?;
+        self.map = None;
+        ::backend::std_file::allocate(&self.file, len)?;
+        self.remap().map_err(RafError::from)
+    }
+
+    /// Applies `advice` to the mapped pages with `madvise`; the part of the range past the end of
+    /// the file is ignored.
+    #[cfg(unix)]
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        let map = match self.map {
+            Some(ref map) if at < map.len() as u64 => map,
+            _ => return Ok(())
+        };
+        let at = at as usize;
+        let len = if len == 0 { map.len() - at } else { cmp::min(len, (map.len() - at) as u64) as usize };
+        let advice = match advice {
+            Advice::Normal => memmap2::Advice::Normal,
+            Advice::Sequential => memmap2::Advice::Sequential,
+            Advice::Random => memmap2::Advice::Random,
+            Advice::WillNeed => memmap2::Advice::WillNeed,
+            // Safe for a shared file mapping: dropped pages are read back from the page cache or
+            // the file, so no writes are lost.
+            Advice::DontNeed => return unsafe {
+                map.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, at, len).map_err(RafError::from)
+            },
+        };
+        map.advise_range(advice, at, len).map_err(RafError::from)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        ::backend::std_file::punch_hole(&self.file, at, len)
+    }
+
+    /// Synchronously writes modified pages of the mapping back to the file.
+    fn flush(&mut self) -> Result<(), RafError> {
+        match self.map {
+            Some(ref map) => map.flush().map_err(RafError::from),
+            None => Ok(())
+        }
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.flush()?;
+        self.file.sync_data().map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.flush()?;
+        self.file.sync_all().map_err(RafError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backend::mmap::MmapRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn grows_and_remaps() {
+        let path = env::temp_dir().join("raf_mmap.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let mut raf: MmapRandomAccessFile = OpenRandomAccessFile::new(path).unwrap();
+            assert!(raf.is_empty().unwrap());
+            assert_eq!(raf.append(b"mapped").unwrap(), 0);
+            raf.write_at(10, b"!").unwrap();
+            assert_eq!(raf.len().unwrap(), 11);
+            assert_eq!(raf.at(2).unwrap(), b'p');
+            #[cfg(unix)]
+            {
+                raf.advise(0, 0, ::Advice::DontNeed).unwrap();
+                assert_eq!(raf.at(10).unwrap(), b'!');
+            }
+            let mut clone = raf.try_clone().unwrap();
+            clone.write_at(0, b"M").unwrap();
+            assert_eq!(raf.at(0).unwrap(), b'M');
+            clone.write_at(0, b"m").unwrap();
+            raf.flush().unwrap();
+        }
+        assert_eq!(fs::read(path).unwrap(), b"mapped\0\0\0\0!");
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,16 @@
+//! The types this crate implements `RandomAccessFile` for. `std_file` and the direct I/O `direct`
+//! backend are always available, as is `block_device` on Unix; the libc based `cfile` and memory
+//! mapped `mmap` backends are behind the features of the same name, as is the Linux only io_uring
+//! `uring` backend.
+
+#[cfg(unix)]
+pub mod block_device;
+#[cfg(feature = "cfile")]
+pub mod cfile;
+pub mod direct;
+pub mod memory;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod std_file;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
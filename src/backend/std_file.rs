@@ -0,0 +1,501 @@
+//! `RandomAccessFile` for `std::fs::File`.
+
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use std::cmp;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+#[cfg(unix)]
+use std::io::IoSliceMut;
+#[cfg(not(any(unix, windows)))]
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafOptions;
+use RandomAccessFile;
+#[cfg(any(unix, windows))]
+use SharedRandomAccessFile;
+
+// The smallest IOV_MAX allowed by POSIX; longer lists are handled as a short read or write.
+#[cfg(unix)]
+const MAX_IOVECS: usize = 1024;
+
+#[cfg(unix)]
+fn offset(at: u64) -> Result<libc::off_t, Error> {
+    if at > libc::off_t::MAX as u64 {
+        return Err(Error::new(ErrorKind::InvalidInput, "offset too large for the platform"));
+    }
+    Ok(at as libc::off_t)
+}
+
+/// Copies with `copy_file_range(2)`. Returns `None`, having copied nothing, if the kernel can't
+/// copy between these two files so the caller should fall back to reading and writing.
+#[cfg(target_os = "linux")]
+pub(crate) fn copy_file_range(src: &File, src_off: u64, dst: &File, dst_off: u64, len: u64)
+    -> Result<Option<u64>, RafError> {
+    let mut off_in = offset(src_off)?;
+    let mut off_out = offset(dst_off)?;
+    let mut copied = 0u64;
+    while copied < len {
+        let chunk = cmp::min(len - copied, isize::MAX as u64) as usize;
+        let n = unsafe {
+            libc::copy_file_range(src.as_raw_fd(), &mut off_in, dst.as_raw_fd(), &mut off_out, chunk, 0)
+        };
+        if n < 0 {
+            let e = Error::last_os_error();
+            match e.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EXDEV) | Some(libc::ENOSYS) | Some(libc::EINVAL) | Some(libc::EOPNOTSUPP)
+                    if copied == 0 => return Ok(None),
+                _ => return Err(e.into())
+            }
+        }
+        if n == 0 {
+            break;
+        }
+        copied += n as u64;
+    }
+    Ok(Some(copied))
+}
+
+/// Zeroes a range with `fallocate(FALLOC_FL_ZERO_RANGE)`. Returns false, having done nothing, if
+/// the file system doesn't support it.
+#[cfg(target_os = "linux")]
+pub(crate) fn zero_range(file: &File, at: u64, len: u64) -> Result<bool, RafError> {
+    fallocate(file, libc::FALLOC_FL_ZERO_RANGE, at, len)
+}
+
+/// Deallocates a range with `fallocate(FALLOC_FL_PUNCH_HOLE)`, failing with `Unsupported` if the
+/// file system can't.
+#[cfg(target_os = "linux")]
+pub(crate) fn punch_hole(file: &File, at: u64, len: u64) -> Result<(), RafError> {
+    if fallocate(file, libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, at, len)? {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::Unsupported, "the file system does not support punching holes").into())
+    }
+}
+
+/// Reserves storage for the first `len` bytes of the file with `posix_fallocate`, extending it if
+/// it is shorter.
+#[cfg(target_os = "linux")]
+pub(crate) fn allocate(file: &File, len: u64) -> Result<(), RafError> {
+    if len == 0 {
+        return Ok(());
+    }
+    loop {
+        match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, offset(len)?) } {
+            0 => return Ok(()),
+            libc::EINTR => continue,
+            code => return Err(Error::from_raw_os_error(code).into())
+        }
+    }
+}
+
+/// Passes `advice` for a range on to the kernel with `posix_fadvise`.
+#[cfg(target_os = "linux")]
+pub(crate) fn fadvise(file: &File, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+    let advice = match advice {
+        Advice::Normal => libc::POSIX_FADV_NORMAL,
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_FADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), offset(at)?, offset(len)?, advice) } {
+        0 => Ok(()),
+        code => Err(Error::from_raw_os_error(code).into())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn fallocate(file: &File, mode: libc::c_int, at: u64, len: u64) -> Result<bool, RafError> {
+    if len == 0 {
+        return Ok(true);
+    }
+    let len = match at.checked_add(len) {
+        Some(_) => offset(len)?,
+        None => return Err(Error::new(ErrorKind::InvalidInput, "range past the end of the address space").into())
+    };
+    loop {
+        if unsafe { libc::fallocate(file.as_raw_fd(), mode, offset(at)?, len) } == 0 {
+            return Ok(true);
+        }
+        let e = Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => return Ok(false),
+            _ => return Err(e.into())
+        }
+    }
+}
+
+impl OpenRandomAccessFile for File {
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<File, RafError> {
+        let path = path.as_ref();
+        options.to_open_options().open(path).map_err(|e| RafError::from(e).with_path(path))
+    }
+}
+
+/// On Unix `read_at` and `write_at` are positioned reads and writes (`pread`/`pwrite`) that leave
+/// the file cursor alone; on Windows they use `seek_read`/`seek_write`, which move it. Elsewhere,
+/// such as on WASI (`wasm32-wasip1`), they seek and then read or write.
+impl RandomAccessFile for File {
+    #[cfg(unix)]
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        ::std::os::unix::fs::FileExt::read_at(self, dat, at).map_err(RafError::from)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        ::std::os::windows::fs::FileExt::seek_read(self, dat, at).map_err(RafError::from)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.seek(SeekFrom::Start(at))?;
+        self.read(dat).map_err(RafError::from)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        ::std::os::unix::fs::FileExt::write_at(self, data, at).map_err(RafError::from)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        ::std::os::windows::fs::FileExt::seek_write(self, data, at).map_err(RafError::from)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.seek(SeekFrom::Start(at))?;
+        self.write(data).map_err(RafError::from)
+    }
+
+    #[cfg(unix)]
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        // IoSliceMut is guaranteed to be ABI compatible with iovec on Unix.
+        let count = cmp::min(bufs.len(), MAX_IOVECS) as libc::c_int;
+        let n = unsafe {
+            libc::preadv(self.as_raw_fd(), bufs.as_mut_ptr() as *const libc::iovec, count, offset(at)?)
+        };
+        if n < 0 {
+            return Err(Error::last_os_error().into());
+        }
+        Ok(n as usize)
+    }
+
+    #[cfg(unix)]
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        let count = cmp::min(bufs.len(), MAX_IOVECS) as libc::c_int;
+        let n = unsafe {
+            libc::pwritev(self.as_raw_fd(), bufs.as_ptr() as *const libc::iovec, count, offset(at)?)
+        };
+        if n < 0 {
+            return Err(Error::last_os_error().into());
+        }
+        Ok(n as usize)
+    }
+
+    /// Duplicates the file descriptor (or handle, on Windows). The clone shares the file offset
+    /// used by `Read`, `Write` and `Seek` with the original; positioned reads and writes don't
+    /// use it.
+    fn try_clone(&self) -> Result<File, RafError> {
+        File::try_clone(self).map_err(RafError::from)
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        Some(self)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        allocate(self, len)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        fadvise(self, at, len, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        punch_hole(self, at, len)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.seek(SeekFrom::End(0))?;
+        self.write_all(data)?;
+        Ok(at)
+    }
+
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        let at = self.seek(SeekFrom::End(0))?;
+        let mut owned = bufs.to_vec();
+        let mut bufs = &mut owned[..];
+        while !bufs.is_empty() {
+            match self.write_vectored(bufs) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer").into()),
+                Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into())
+            }
+        }
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        File::metadata(self).map(|m| m.len()).map_err(RafError::from)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        File::set_len(self, len).map_err(RafError::from)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        Write::flush(self).map_err(RafError::from)
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        File::sync_data(self).map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        File::sync_all(self).map_err(RafError::from)
+    }
+}
+
+#[cfg(unix)]
+impl SharedRandomAccessFile for File {
+    fn read_at_shared(&self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        ::std::os::unix::fs::FileExt::read_at(self, dat, at).map_err(RafError::from)
+    }
+
+    fn write_at_shared(&self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        ::std::os::unix::fs::FileExt::write_at(self, data, at).map_err(RafError::from)
+    }
+}
+
+#[cfg(windows)]
+impl SharedRandomAccessFile for File {
+    fn read_at_shared(&self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        ::std::os::windows::fs::FileExt::seek_read(self, dat, at).map_err(RafError::from)
+    }
+
+    fn write_at_shared(&self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        ::std::os::windows::fs::FileExt::seek_write(self, data, at).map_err(RafError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Advice;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+    use std::io::IoSlice;
+    use std::io::IoSliceMut;
+    #[cfg(any(unix, windows))]
+    use std::sync::Arc;
+    use std::thread;
+    #[cfg(any(unix, windows))]
+    use SharedRandomAccessFile;
+    use StorageKind;
+
+    #[test]
+    fn file_positioned_io() {
+        let path = env::temp_dir().join("raf_file_backend.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        assert_eq!(raf.write_at(4, b"world").unwrap(), 5);
+        assert_eq!(raf.write_at(0, b"hey ").unwrap(), 4);
+        assert_eq!(raf.append(b"!").unwrap(), 9);
+
+        let mut buf = [0u8; 10];
+        assert_eq!(raf.read_at(0, &mut buf).unwrap(), 10);
+        assert_eq!(&buf, b"hey world!");
+        assert_eq!(raf.read_at(10, &mut buf).unwrap(), 0);
+        assert_eq!(raf.at(4).unwrap(), b'w');
+
+        assert_eq!(raf.len().unwrap(), 10);
+        raf.set_len(3).unwrap();
+        raf.sync_all().unwrap();
+        assert_eq!(raf.len().unwrap(), 3);
+
+        let bufs = [IoSlice::new(b"head"), IoSlice::new(b"er+body")];
+        assert_eq!(raf.write_at_vectored(1, &bufs).unwrap(), 11);
+        let (mut a, mut b) = ([0u8; 7], [0u8; 10]);
+        assert_eq!(raf.read_at_vectored(0, &mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)]).unwrap(), 12);
+        assert_eq!((&a, &b[..5]), (b"hheader", &b"+body"[..]));
+
+        let record = [IoSlice::new(b"[h]"), IoSlice::new(b""), IoSlice::new(b"[b]")];
+        assert_eq!(raf.append_vectored(&record).unwrap(), 12);
+        let mut tail = [0u8; 6];
+        raf.read_exact_at(12, &mut tail).unwrap();
+        assert_eq!(&tail, b"[h][b]");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    #[cfg(any(unix, windows))]
+    fn shared_reads_from_threads() {
+        let path = env::temp_dir().join("raf_file_shared.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let file: File = OpenRandomAccessFile::new(path).unwrap();
+        file.write_all_at_shared(0, &(0..64u8).collect::<Vec<u8>>()).unwrap();
+        let file = Arc::new(file);
+        let threads: Vec<_> = (0..4).map(|i| {
+            let file = file.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; 16];
+                file.read_exact_at_shared(i * 16, &mut buf).unwrap();
+                buf[0]
+            })
+        }).collect();
+        let firsts: Vec<u8> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(firsts, vec![0, 16, 32, 48]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn copy_range_between_backends() {
+        let src_path = env::temp_dir().join("raf_copy_src.bin");
+        let dst_path = env::temp_dir().join("raf_copy_dst.bin");
+        let _ = fs::remove_file(&src_path);
+        let _ = fs::remove_file(&dst_path);
+        let mut src: File = OpenRandomAccessFile::new(&src_path).unwrap();
+        let mut dst: File = OpenRandomAccessFile::new(&dst_path).unwrap();
+        src.append(b"0123456789").unwrap();
+
+        assert_eq!(src.copy_range(2, &mut dst, 1, 5).unwrap(), 5);
+        assert_eq!(fs::read(&dst_path).unwrap(), b"\x0023456");
+        assert_eq!(src.copy_range(8, &mut dst, 0, 100).unwrap(), 2);
+
+        let mut mem = MemoryRandomAccessFile::new();
+        assert_eq!(src.copy_range(0, &mut mem, 0, 4).unwrap(), 4);
+        assert_eq!(mem.as_slice(), b"0123");
+        let _ = fs::remove_file(&src_path);
+        let _ = fs::remove_file(&dst_path);
+    }
+
+    #[test]
+    fn fill_at_zeroes_and_fills() {
+        let path = env::temp_dir().join("raf_fill.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.fill_at(0, 200_000, b'x').unwrap();
+        raf.fill_at(10, 100_000, 0).unwrap();
+        assert_eq!(raf.len().unwrap(), 200_000);
+        let data = fs::read(&path).unwrap();
+        assert!(data[..10].iter().all(|&b| b == b'x'));
+        assert!(data[10..100_010].iter().all(|&b| b == 0));
+        assert!(data[100_010..].iter().all(|&b| b == b'x'));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn punch_hole_keeps_size() {
+        let path = env::temp_dir().join("raf_punch.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.fill_at(0, 3 * 4096, b'a').unwrap();
+        match raf.punch_hole(4096, 4096) {
+            Err(ref e) if e.kind() == ErrorKind::Unsupported => (),
+            result => {
+                result.unwrap();
+                let data = fs::read(&path).unwrap();
+                assert_eq!(data.len(), 3 * 4096);
+                assert!(data[4096..8192].iter().all(|&b| b == 0));
+                assert_eq!(data[8192], b'a');
+            }
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn allocate_extends_only() {
+        let path = env::temp_dir().join("raf_allocate.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.append(b"abc").unwrap();
+        raf.allocate(1 << 20).unwrap();
+        assert_eq!(raf.len().unwrap(), 1 << 20);
+        raf.allocate(10).unwrap();
+        assert_eq!(raf.len().unwrap(), 1 << 20);
+        assert_eq!(raf.at(1).unwrap(), b'b');
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn advise_accepts_every_hint() {
+        let path = env::temp_dir().join("raf_advise.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.append(&[7u8; 4096]).unwrap();
+        for &advice in &[Advice::Sequential, Advice::Random, Advice::WillNeed, Advice::DontNeed, Advice::Normal] {
+            raf.advise(0, 0, advice).unwrap();
+            raf.advise(100, 1 << 20, advice).unwrap();
+        }
+        assert_eq!(raf.at(4095).unwrap(), 7);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn try_clone_shares_the_file() {
+        let path = env::temp_dir().join("raf_try_clone.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.append(b"one").unwrap();
+        // The clone must not depend on the path, which Unix lets us remove while the file is open.
+        #[cfg(unix)]
+        fs::remove_file(&path).unwrap();
+
+        let mut clone = RandomAccessFile::try_clone(&raf).unwrap();
+        let writer = thread::spawn(move || {
+            clone.write_all_at(3, b"two").unwrap();
+            clone
+        });
+        let mut clone = writer.join().unwrap();
+        assert_eq!(raf.len().unwrap(), 6);
+        raf.set_len(2).unwrap();
+        assert_eq!(clone.len().unwrap(), 2);
+        assert_eq!(MemoryRandomAccessFile::new().try_clone().unwrap_err().kind(), ErrorKind::Unsupported);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn metadata_describes_the_storage() {
+        let path = env::temp_dir().join("raf_metadata.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.append(b"meta").unwrap();
+        let metadata = RandomAccessFile::metadata(&mut raf).unwrap();
+        assert_eq!(metadata.len, 4);
+        assert_eq!(metadata.kind, StorageKind::File);
+        assert!(metadata.modified.is_some());
+        assert!(!metadata.permissions.unwrap().readonly());
+
+        let metadata = MemoryRandomAccessFile::from_vec(vec![1, 2]).metadata().unwrap();
+        assert_eq!((metadata.len, metadata.kind), (2, StorageKind::Memory));
+        let _ = fs::remove_file(&path);
+    }
+}
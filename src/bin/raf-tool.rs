@@ -0,0 +1,103 @@
+extern crate random_access_file;
+
+use random_access_file::ReadOnlyRaf;
+use random_access_file::open_sealed;
+use random_access_file::read_seal_footer;
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::process;
+
+static USAGE: &str = "usage:
+    raf-tool header <file>                    print the size and seal footer of a file
+    raf-tool hexdump <file> [offset] [len]    hexdump len bytes (default 256) starting at offset
+    raf-tool verify <file>                    verify the footer and checksum of a sealed file";
+
+fn parse_number(arg: Option<&String>, default: u64) -> Result<u64, Error> {
+    match arg {
+        None => Ok(default),
+        Some(s) => {
+            let parsed = match s.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => s.parse()
+            };
+            parsed.map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid number '{}'", s)))
+        }
+    }
+}
+
+fn header(path: &str) -> Result<(), Error> {
+    let mut file = File::open(path)?;
+    println!("file:         {}", path);
+    println!("size:         {} bytes", file.metadata()?.len());
+    match read_seal_footer(&mut file) {
+        Ok(footer) => {
+            println!("sealed:       yes");
+            println!("data length:  {} bytes", footer.len);
+            println!("records:      {}", footer.record_count);
+            println!("checksum:     {:08x} (crc32)", footer.checksum);
+        },
+        Err(ref e) if e.kind() == ErrorKind::InvalidData => println!("sealed:       no"),
+        Err(e) => return Err(e.into())
+    }
+    Ok(())
+}
+
+fn hexdump(path: &str, offset: u64, len: u64) -> Result<(), Error> {
+    let mut file = ReadOnlyRaf::<File>::open(path)?;
+    // Never more than the file holds, however large `len` is.
+    let len = cmp::min(len, file.len()?.saturating_sub(offset)) as usize;
+    let mut buf = vec![0u8; len];
+    let mut filled = 0;
+    while filled < len {
+        let at = offset.checked_add(filled as u64)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "offset out of range"))?;
+        let read = file.read_at(at, &mut buf[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    for (i, line) in buf[..filled].chunks(16).enumerate() {
+        let mut hex = String::with_capacity(16 * 3);
+        let mut ascii = String::with_capacity(16);
+        for (j, b) in line.iter().enumerate() {
+            if j == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", b));
+            ascii.push(if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' });
+        }
+        println!("{:08x}  {:<49} |{}|", offset + i as u64 * 16, hex, ascii);
+    }
+    Ok(())
+}
+
+fn verify(path: &str) -> Result<(), Error> {
+    let (_, footer) = open_sealed(path)?;
+    println!("{}: OK ({} bytes, {} records, crc32 {:08x})", path, footer.len, footer.record_count, footer.checksum);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match (args.first().map(|s| s.as_str()), args.get(1)) {
+        (Some("header"), Some(path)) => header(path),
+        (Some("hexdump"), Some(path)) => {
+            parse_number(args.get(2), 0)
+                .and_then(|offset| parse_number(args.get(3), 256).map(|len| (offset, len)))
+                .and_then(|(offset, len)| hexdump(path, offset, len))
+        },
+        (Some("verify"), Some(path)) => verify(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("raf-tool: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,169 @@
+use std::cmp;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use RafError;
+use RandomAccessFile;
+use Serialize;
+
+/// Identifies a blob written with `begin_blob`: the offset and length of its bytes in the file.
+/// Serializes as two `u64`s so it can be stored in other values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlobId {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl Serialize for BlobId {
+    type DeserializeOutput = BlobId;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
+        self.offset.serialize(to)?;
+        self.len.serialize(to)
+    }
+    fn deserialize(from: &mut dyn Read) -> Result<BlobId, Error> {
+        Ok(BlobId {
+            offset: u64::deserialize(from)?,
+            len: u64::deserialize(from)?,
+        })
+    }
+}
+
+/// Starts streaming a blob onto the end of `raf`. Nothing is buffered in memory: every `write` on
+/// the returned writer goes straight to the file, and `finish` yields the id of the blob. If the
+/// writer is dropped without calling `finish`, the bytes written so far are left in the file
+/// unreferenced.
+pub fn begin_blob<R: RandomAccessFile>(raf: &mut R) -> Result<BlobWriter<'_, R>, RafError> {
+    let offset = raf.len()?;
+    Ok(BlobWriter { raf, offset, len: 0 })
+}
+
+/// Opens a blob for reading. The reader only sees the bytes of the blob; its positions are
+/// relative to the start of the blob.
+pub fn open_blob<R: RandomAccessFile>(raf: &mut R, id: BlobId) -> BlobReader<'_, R> {
+    BlobReader { raf, id, pos: 0 }
+}
+
+pub struct BlobWriter<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a, R: RandomAccessFile + 'a> BlobWriter<'a, R> {
+    /// The number of bytes written so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn finish(self) -> BlobId {
+        BlobId { offset: self.offset, len: self.len }
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Write for BlobWriter<'a, R> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.raf.write_all_at(self.offset + self.len, buf)?;
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub struct BlobReader<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    id: BlobId,
+    pos: u64,
+}
+
+impl<'a, R: RandomAccessFile + 'a> BlobReader<'a, R> {
+    pub fn id(&self) -> BlobId {
+        self.id
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Read for BlobReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.pos >= self.id.len {
+            return Ok(0);
+        }
+        let want = cmp::min(buf.len() as u64, self.id.len - self.pos) as usize;
+        let read = self.raf.read_at(self.id.offset + self.pos, &mut buf[..want])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Seek for BlobReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.id.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match target {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            },
+            None => Err(Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blob::begin_blob;
+    use blob::BlobId;
+    use blob::open_blob;
+    use BufRandomAccessFile;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Read;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    #[test]
+    fn stream_in_and_out() {
+        let path = env::temp_dir().join("raf_blob.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        raf.append(b"header").unwrap();
+
+        let mut writer = begin_blob(&mut raf).unwrap();
+        for i in 0..100u8 {
+            writer.write_all(&[i; 10]).unwrap();
+        }
+        let id = writer.finish();
+        assert_eq!((id.offset, id.len), (6, 1000));
+        raf.append(b"trailer").unwrap();
+
+        let mut reader = open_blob(&mut raf, id);
+        reader.seek(SeekFrom::Start(995)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![99u8; 5]);
+        let _ = fs::remove_file(path);
+
+        // Any backend will do, not only ones with a cursor.
+        let mut raf = BufRandomAccessFile::new(MemoryRandomAccessFile::from_vec(b"head".to_vec())).unwrap();
+        let mut writer = begin_blob(&mut raf).unwrap();
+        writer.write_all(b"buffered").unwrap();
+        assert_eq!(writer.finish(), BlobId { offset: 4, len: 8 });
+    }
+}
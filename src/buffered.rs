@@ -0,0 +1,285 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+use WriteBack;
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+const DEFAULT_BLOCKS: usize = 256;
+
+#[derive(Debug)]
+struct Block {
+    // Always min(block_size, len - start of the block) bytes long.
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// A `RandomAccessFile` that caches the file in fixed-size blocks, so that many small scattered
+/// `read_at` and `write_at` calls turn into a few block-sized reads and writes of the inner file.
+/// Reads load whole blocks; writes go to the cached blocks and reach the inner file when a dirty
+/// block is evicted to make room, or on `flush`, `sync_data` or `sync_all`, which write back every
+/// dirty block. The least recently used block is evicted first.
+///
+/// Dropping the handle writes back dirty blocks, ignoring errors; call `flush` to see them. The
+/// inner file must not be changed through other handles while it is wrapped, or the cache goes
+/// stale.
+#[derive(Debug)]
+pub struct BufRandomAccessFile<R: RandomAccessFile> {
+    inner: R,
+    block_size: usize,
+    max_blocks: usize,
+    blocks: HashMap<u64, Block>,
+    len: u64,
+    clock: u64,
+}
+
+impl<R: RandomAccessFile> BufRandomAccessFile<R> {
+    /// Wraps `inner` with a cache of 256 blocks of 4 KiB.
+    pub fn new(inner: R) -> Result<BufRandomAccessFile<R>, RafError> {
+        BufRandomAccessFile::with_capacity(DEFAULT_BLOCK_SIZE, DEFAULT_BLOCKS, inner)
+    }
+
+    /// Wraps `inner` with a cache of `blocks` blocks of `block_size` bytes. Panics if either is 0.
+    pub fn with_capacity(block_size: usize, blocks: usize, mut inner: R) -> Result<BufRandomAccessFile<R>, RafError> {
+        assert!(block_size != 0 && blocks != 0, "the block size and number of blocks must be non-zero");
+        let len = inner.len()?;
+        Ok(BufRandomAccessFile { inner, block_size, max_blocks: blocks, blocks: HashMap::new(), len, clock: 0 })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The number of bytes in dirty blocks, which have not been written to the inner file yet.
+    pub fn dirty_bytes(&self) -> u64 {
+        self.blocks.values().filter(|block| block.dirty).map(|block| block.data.len() as u64).sum()
+    }
+
+    /// The inner file. Data in dirty blocks has not reached it yet.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Writes back every dirty block, without flushing the inner file.
+    pub fn write_back(&mut self) -> Result<(), RafError> {
+        let mut dirty: Vec<u64> = self.blocks.iter().filter(|&(_, block)| block.dirty).map(|(&index, _)| index).collect();
+        dirty.sort_unstable();
+        for index in dirty {
+            self.write_back_block(index)?;
+        }
+        Ok(())
+    }
+
+    fn write_back_block(&mut self, index: u64) -> Result<(), RafError> {
+        let start = index * self.block_size as u64;
+        if let Some(block) = self.blocks.get_mut(&index) {
+            if block.dirty {
+                self.inner.write_all_at(start, &block.data)?;
+                block.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes back and forgets every block, for operations that change the inner file directly.
+    fn invalidate(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.blocks.clear();
+        Ok(())
+    }
+
+    fn evict_one(&mut self) -> Result<(), RafError> {
+        let victim = self.blocks.iter().min_by_key(|&(_, block)| block.last_used).map(|(&index, _)| index);
+        if let Some(index) = victim {
+            self.write_back_block(index)?;
+            self.blocks.remove(&index);
+        }
+        Ok(())
+    }
+
+    // The cached block `index`, loading it unless `overwrite` says the caller is about to replace
+    // all of its contents.
+    fn block(&mut self, index: u64, overwrite: bool) -> Result<&mut Block, RafError> {
+        self.clock += 1;
+        if !self.blocks.contains_key(&index) {
+            if self.blocks.len() >= self.max_blocks {
+                self.evict_one()?;
+            }
+            let start = index * self.block_size as u64;
+            let size = cmp::min(self.block_size as u64, self.len.saturating_sub(start)) as usize;
+            let mut data = vec![0u8; size];
+            if !overwrite {
+                let mut filled = 0;
+                // Past the end of the inner file, which buffered writes may have extended, the
+                // block stays zero filled.
+                while filled < size {
+                    match self.inner.read_at(start + filled as u64, &mut data[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                        Err(e) => return Err(e)
+                    }
+                }
+            }
+            self.blocks.insert(index, Block { data, dirty: false, last_used: 0 });
+        }
+        let clock = self.clock;
+        let block = self.blocks.get_mut(&index).expect("block was just inserted");
+        block.last_used = clock;
+        Ok(block)
+    }
+}
+
+impl<R: RandomAccessFile> Drop for BufRandomAccessFile<R> {
+    fn drop(&mut self) {
+        let _ = self.write_back();
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for BufRandomAccessFile<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let n = cmp::min(self.len.saturating_sub(at), dat.len() as u64) as usize;
+        let block_size = self.block_size as u64;
+        let mut done = 0;
+        while done < n {
+            let pos = at + done as u64;
+            let offset = (pos % block_size) as usize;
+            let block = self.block(pos / block_size, false)?;
+            let take = cmp::min(n - done, block.data.len() - offset);
+            dat[done..done + take].copy_from_slice(&block.data[offset..offset + take]);
+            done += take;
+        }
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        if at.checked_add(data.len() as u64).is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the address space").into());
+        }
+        let block_size = self.block_size as u64;
+        let mut done = 0;
+        while done < data.len() {
+            let pos = at + done as u64;
+            let offset = (pos % block_size) as usize;
+            let take = cmp::min(data.len() - done, self.block_size - offset);
+            let end = pos + take as u64;
+            if end > self.len {
+                // Only the block holding the old end can be cached and shorter than a full block;
+                // it grows with zeros, as the file would.
+                if self.len > 0 {
+                    let last = (self.len - 1) / block_size;
+                    if let Some(block) = self.blocks.get_mut(&last) {
+                        block.data.resize(cmp::min(block_size, end - last * block_size) as usize, 0);
+                    }
+                }
+                self.len = end;
+            }
+            let overwrite = offset == 0 && take == self.block_size;
+            let block = self.block(pos / block_size, overwrite)?;
+            block.data[offset..offset + take].copy_from_slice(&data[done..done + take]);
+            block.dirty = true;
+            done += take;
+        }
+        Ok(data.len())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.len;
+        self.write_all_at(at, data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.len)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.invalidate()?;
+        self.inner.set_len(len)?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Writes back every dirty block and flushes the inner file.
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.sync_all()
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata { len: self.len, ..self.inner.metadata()? })
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.invalidate()?;
+        self.inner.punch_hole(at, len)
+    }
+}
+
+impl<R: RandomAccessFile> WriteBack for BufRandomAccessFile<R> {
+    fn dirty_bytes(&self) -> u64 {
+        BufRandomAccessFile::dirty_bytes(self)
+    }
+
+    fn write_back(&mut self) -> Result<(), RafError> {
+        BufRandomAccessFile::write_back(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffered::BufRandomAccessFile;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+
+    #[test]
+    fn caches_blocks_and_writes_back() {
+        let inner = MemoryRandomAccessFile::from_vec((0..20).collect());
+        let mut raf = BufRandomAccessFile::with_capacity(8, 2, inner).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(raf.read_at(6, &mut buf).unwrap(), 4);
+        assert_eq!(buf, [6, 7, 8, 9]);
+
+        raf.write_all_at(1, b"ab").unwrap();
+        assert_eq!(raf.at(2).unwrap(), b'b');
+        assert_eq!(raf.get_ref().as_slice()[1], 1);
+        assert_eq!(raf.dirty_bytes(), 8);
+
+        // Touching a third block evicts the least recently used one, the dirty block 0.
+        raf.read_at(9, &mut buf).unwrap();
+        raf.at(17).unwrap();
+        assert_eq!(&raf.get_ref().as_slice()[1..3], b"ab");
+
+        assert_eq!(raf.append(b"xyz").unwrap(), 20);
+        raf.write_all_at(30, b"!").unwrap();
+        assert_eq!(raf.len().unwrap(), 31);
+        let mut tail = [0xffu8; 12];
+        raf.read_exact_at(19, &mut tail).unwrap();
+        assert_eq!(&tail, b"\x13xyz\0\0\0\0\0\0\0!");
+
+        raf.flush().unwrap();
+        assert_eq!(raf.dirty_bytes(), 0);
+        assert_eq!(&raf.get_ref().as_slice()[19..], &tail[..]);
+        raf.set_len(5).unwrap();
+        assert_eq!(raf.read_at(0, &mut [0u8; 8]).unwrap(), 5);
+    }
+}
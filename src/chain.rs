@@ -0,0 +1,261 @@
+use checksum::Crc32;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+use Offset;
+use RandomAccessFile;
+use Serialize;
+
+static NIL: u64 = u64::MAX;
+static NEXT_SIZE: u64 = 8;
+static CHECKSUM_HEADER_SIZE: u64 = 8 + 4;
+
+/// Marker type for an on-disk chain node holding a `T`; it is never constructed, only used as the
+/// target type of `NodeOffset<T>`.
+pub struct Node<T> {
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// The location of a chain node holding a `T`.
+pub type NodeOffset<T> = Offset<Node<T>>;
+
+fn corrupt(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Every node takes up at least its next pointer, so a chain can't have more nodes than this and
+// a walk that visits more has run into a cycle.
+fn max_nodes<R: RandomAccessFile>(raf: &mut R) -> Result<u64, Error> {
+    Ok(raf.len()? / NEXT_SIZE)
+}
+
+/// Helpers for singly linked chains of nodes stored in a file: the layout behind overflow pages,
+/// free lists and version chains. Each node is laid out as
+///
+/// ```text
+/// next: u64 (u64::MAX for the end of the chain)
+/// [len: u64, crc32: u32]   only for checksummed chains
+/// value: T
+/// ```
+///
+/// The caller decides where nodes live; `Chain` only reads and links them.
+#[derive(Debug)]
+pub struct Chain<T> {
+    checksummed: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize> Chain<T> {
+    pub fn new() -> Chain<T> {
+        Chain { checksummed: false, _marker: PhantomData }
+    }
+
+    /// A chain whose nodes carry a CRC-32 of their value, verified every time the value is read.
+    pub fn checksummed() -> Chain<T> {
+        Chain { checksummed: true, _marker: PhantomData }
+    }
+
+    /// Writes a node holding `value` at `at`, linked to `next`.
+    pub fn write_node<R: RandomAccessFile>(&self, raf: &mut R, at: NodeOffset<T>, value: &T,
+                                           next: Option<NodeOffset<T>>) -> Result<(), Error> {
+        let mut encoded = Vec::new();
+        value.serialize(&mut encoded)?;
+        let mut node = Vec::with_capacity(encoded.len() + (NEXT_SIZE + CHECKSUM_HEADER_SIZE) as usize);
+        next.map_or(NIL, |n| n.get()).serialize(&mut node)?;
+        if self.checksummed {
+            let mut crc = Crc32::new();
+            crc.update(&encoded);
+            (encoded.len() as u64).serialize(&mut node)?;
+            crc.finish().serialize(&mut node)?;
+        }
+        node.extend_from_slice(&encoded);
+        raf.write_all_at(at.get(), &node).map_err(Error::from)
+    }
+
+    /// Writes a node holding `value` at `at` in front of `head`, returning the new head.
+    pub fn push_front<R: RandomAccessFile>(&self, raf: &mut R, at: NodeOffset<T>, value: &T,
+                                           head: Option<NodeOffset<T>>) -> Result<NodeOffset<T>, Error> {
+        self.write_node(raf, at, value, head)?;
+        Ok(at)
+    }
+
+    /// The node following `node`, if any.
+    pub fn next<R: RandomAccessFile>(&self, raf: &mut R, node: NodeOffset<T>) -> Result<Option<NodeOffset<T>>, Error> {
+        let next = Offset::<u64>::new(node.get()).read(raf)?;
+        Ok(if next == NIL { None } else { Some(Offset::new(next)) })
+    }
+
+    /// Reads the value held by `node`. For checksummed chains a mismatch, or a length running past
+    /// the end of the file, is reported as an error of kind `InvalidData`.
+    pub fn value<R: RandomAccessFile>(&self, raf: &mut R, node: NodeOffset<T>) -> Result<T::DeserializeOutput, Error> {
+        let at = node.get().checked_add(NEXT_SIZE).ok_or_else(|| corrupt("chain node offset out of range"))?;
+        if !self.checksummed {
+            return Offset::<T>::new(at).read(raf);
+        }
+        let len = Offset::<u64>::new(at).read(raf)?;
+        let expected = Offset::<u32>::new(at + 8).read(raf)?;
+        // The length comes from the file, so check it before allocating for it.
+        let start = at + CHECKSUM_HEADER_SIZE;
+        if len > raf.len()?.saturating_sub(start) {
+            return Err(corrupt("chain node length runs past the end of the file"));
+        }
+        let mut encoded = vec![0u8; len as usize];
+        raf.read_exact_at(start, &mut encoded)?;
+        let mut crc = Crc32::new();
+        crc.update(&encoded);
+        if crc.finish() != expected {
+            return Err(corrupt("chain node failed checksum verification"));
+        }
+        T::deserialize(&mut &encoded[..])
+    }
+
+    /// Links `node` to `next`, splicing whatever chain starts at `next` in after it. Passing `None`
+    /// terminates the chain at `node`.
+    pub fn set_next<R: RandomAccessFile>(&self, raf: &mut R, node: NodeOffset<T>,
+                                         next: Option<NodeOffset<T>>) -> Result<(), Error> {
+        Offset::<u64>::new(node.get()).write(raf, &next.map_or(NIL, |n| n.get()))
+    }
+
+    /// Terminates the chain at `node`, returning the head of the detached remainder.
+    pub fn cut<R: RandomAccessFile>(&self, raf: &mut R, node: NodeOffset<T>) -> Result<Option<NodeOffset<T>>, Error> {
+        let rest = self.next(raf, node)?;
+        self.set_next(raf, node, None)?;
+        Ok(rest)
+    }
+
+    /// The last node of the chain starting at `head`. A chain that loops back on itself is
+    /// reported as an error of kind `InvalidData`.
+    pub fn tail<R: RandomAccessFile>(&self, raf: &mut R, head: NodeOffset<T>) -> Result<NodeOffset<T>, Error> {
+        let mut hops = max_nodes(raf)?;
+        let mut node = head;
+        while let Some(next) = self.next(raf, node)? {
+            if hops == 0 {
+                return Err(corrupt("chain loops back on itself"));
+            }
+            hops -= 1;
+            node = next;
+        }
+        Ok(node)
+    }
+
+    /// Iterates over the nodes of the chain starting at `head`, yielding each node's offset and
+    /// value. Iteration stops after the first error; a chain that loops back on itself ends in
+    /// an error of kind `InvalidData`.
+    pub fn iter<'a, R: RandomAccessFile>(&self, raf: &'a mut R, head: Option<NodeOffset<T>>) -> ChainIter<'a, T, R> {
+        ChainIter { chain: *self, raf, node: head, remaining: None }
+    }
+}
+
+impl<T> Clone for Chain<T> {
+    fn clone(&self) -> Chain<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Chain<T> {}
+
+impl<T: Serialize> Default for Chain<T> {
+    fn default() -> Chain<T> {
+        Chain::new()
+    }
+}
+
+pub struct ChainIter<'a, T: Serialize, R: RandomAccessFile + 'a> {
+    chain: Chain<T>,
+    raf: &'a mut R,
+    node: Option<NodeOffset<T>>,
+    // How many more nodes there can be, worked out on the first call.
+    remaining: Option<u64>,
+}
+
+impl<'a, T: Serialize, R: RandomAccessFile + 'a> Iterator for ChainIter<'a, T, R> {
+    type Item = Result<(NodeOffset<T>, T::DeserializeOutput), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node.take()?;
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => match max_nodes(self.raf) {
+                Ok(max) => max,
+                Err(e) => return Some(Err(e))
+            }
+        };
+        if remaining == 0 {
+            return Some(Err(corrupt("chain loops back on itself")));
+        }
+        self.remaining = Some(remaining - 1);
+        let value = match self.chain.value(self.raf, node) {
+            Ok(value) => value,
+            Err(e) => return Some(Err(e))
+        };
+        match self.chain.next(self.raf, node) {
+            Ok(next) => {
+                self.node = next;
+                Some(Ok((node, value)))
+            },
+            Err(e) => Some(Err(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chain::Chain;
+    use Offset;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn push_iterate_cut_splice() {
+        let path = env::temp_dir().join("raf_chain.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        let chain = Chain::<u32>::new();
+
+        let mut head = None;
+        for i in 0..4u32 {
+            head = Some(chain.push_front(&mut raf, Offset::new(i as u64 * 16), &i, head).unwrap());
+        }
+        let values: Vec<u32> = chain.iter(&mut raf, head).map(|r| r.unwrap().1).collect();
+        assert_eq!(values, vec![3, 2, 1, 0]);
+
+        let second = chain.next(&mut raf, head.unwrap()).unwrap().unwrap();
+        let rest = chain.cut(&mut raf, second).unwrap();
+        assert_eq!(chain.iter(&mut raf, head).count(), 2);
+        let tail = chain.tail(&mut raf, head.unwrap()).unwrap();
+        assert_eq!(tail, second);
+        chain.set_next(&mut raf, tail, rest).unwrap();
+        assert_eq!(chain.iter(&mut raf, head).count(), 4);
+
+        // A corrupt pointer back to the head makes a cycle.
+        let tail = chain.tail(&mut raf, head.unwrap()).unwrap();
+        chain.set_next(&mut raf, tail, head).unwrap();
+        assert_eq!(chain.tail(&mut raf, head.unwrap()).unwrap_err().kind(), ErrorKind::InvalidData);
+        let last = chain.iter(&mut raf, head).last().unwrap();
+        assert_eq!(last.unwrap_err().kind(), ErrorKind::InvalidData);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn checksummed_detects_corruption() {
+        let path = env::temp_dir().join("raf_chain_crc.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        let chain = Chain::<String>::checksummed();
+
+        let head = chain.push_front(&mut raf, Offset::new(0), &String::from("abc"), None).unwrap();
+        assert_eq!(chain.value(&mut raf, head).unwrap(), "abc");
+        raf.write_at(30, b"x").unwrap();
+        assert_eq!(chain.value(&mut raf, head).unwrap_err().kind(), ErrorKind::InvalidData);
+        // A corrupt length is caught before it is allocated for.
+        Offset::<u64>::new(8).write(&mut raf, &(u64::MAX / 2)).unwrap();
+        assert_eq!(chain.value(&mut raf, head).unwrap_err().kind(), ErrorKind::InvalidData);
+        let _ = fs::remove_file(path);
+    }
+}
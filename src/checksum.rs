@@ -0,0 +1,74 @@
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Streaming CRC-32 (IEEE 802.3 polynomial, as used by zlib and PNG).
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { state: 0xFFFF_FFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut c = self.state;
+        for &b in data {
+            c = CRC32_TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8);
+        }
+        self.state = c;
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use checksum::Crc32;
+    use MemoryRandomAccessFile;
+    use RafError;
+    use RandomAccessFile;
+
+    #[test]
+    fn check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn checksums_a_range() {
+        let mut raf = MemoryRandomAccessFile::from_vec(b"xx123456789yy".to_vec());
+        assert_eq!(raf.checksum_range(2, 9).unwrap(), 0xCBF4_3926);
+        assert!(raf.verify_range(2, 9, 0xCBF4_3926).unwrap());
+        assert!(!raf.verify_range(1, 9, 0xCBF4_3926).unwrap());
+        match raf.checksum_range(10, 9) {
+            Err(RafError::ShortRead { offset: 10, expected: 9, read: 3 }) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::ErrorKind;
+use RafError;
+use RandomAccessFile;
+
+/// An iterator over successive chunks of a `RandomAccessFile`, created by
+/// `RandomAccessFile::chunks`. Every chunk is `size` bytes long except the last one, which holds
+/// whatever is left and is never empty. Iteration ends at the end of the file, or after the first
+/// error.
+///
+/// `next` allocates a new `Vec` for every chunk; `next_into` reads into a buffer the caller reuses.
+pub struct Chunks<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    pos: u64,
+    size: usize,
+    done: bool,
+}
+
+impl<'a, R: RandomAccessFile + 'a> Chunks<'a, R> {
+    pub(crate) fn new(raf: &'a mut R, size: usize) -> Chunks<'a, R> {
+        assert!(size != 0, "chunk size must be non-zero");
+        Chunks { raf, pos: 0, size, done: false }
+    }
+
+    /// The offset of the next chunk.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Like `next`, but reads the chunk into `buf`, replacing its contents.
+    pub fn next_into(&mut self, buf: &mut Vec<u8>) -> Option<Result<(), RafError>> {
+        if self.done {
+            return None;
+        }
+        buf.resize(self.size, 0);
+        let mut filled = 0;
+        while filled < self.size {
+            match self.raf.read_at(self.pos + filled as u64, &mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        buf.truncate(filled);
+        self.pos += filled as u64;
+        if filled < self.size {
+            self.done = true;
+        }
+        if filled == 0 {
+            return None;
+        }
+        Some(Ok(()))
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Iterator for Chunks<'a, R> {
+    type Item = Result<Vec<u8>, RafError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = Vec::new();
+        self.next_into(&mut buf).map(|result| result.map(|()| buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+
+    #[test]
+    fn yields_a_short_final_chunk() {
+        let mut raf = MemoryRandomAccessFile::from_vec((0..10).collect());
+        let chunks: Vec<Vec<u8>> = raf.chunks(4).map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(chunks, vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+
+        let mut chunks = raf.chunks(5);
+        let mut buf = Vec::new();
+        let mut seen = 0;
+        while let Some(result) = chunks.next_into(&mut buf) {
+            result.unwrap();
+            assert_eq!(buf.len(), 5);
+            seen += 1;
+        }
+        assert_eq!((seen, chunks.position()), (2, 10));
+        assert!(MemoryRandomAccessFile::new().chunks(3).next().is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+use WriteBack;
+
+/// A `RandomAccessFile` that holds on to written data and merges adjacent and overlapping writes
+/// into larger ones before they reach the inner file, for writers that produce many tiny
+/// contiguous writes, like a serializer writing one field at a time. Pending writes are sent to
+/// the inner file, in offset order, once they add up to more than `threshold` bytes, or on
+/// `write_back`, `flush`, `sync_data` or `sync_all`.
+///
+/// Reads see pending writes. Unlike `BufRandomAccessFile` nothing is cached for reading, so it
+/// costs no memory beyond the pending data. Dropping the handle writes back pending data,
+/// ignoring errors; call `flush` to see them. The inner file must not be changed through other
+/// handles while it is wrapped.
+#[derive(Debug)]
+pub struct CoalescingRaf<R: RandomAccessFile> {
+    inner: R,
+    threshold: usize,
+    // Disjoint, non-adjacent runs of pending data by their starting offset.
+    pending: BTreeMap<u64, Vec<u8>>,
+    pending_bytes: u64,
+    len: u64,
+}
+
+impl<R: RandomAccessFile> CoalescingRaf<R> {
+    pub fn new(mut inner: R, threshold: usize) -> Result<CoalescingRaf<R>, RafError> {
+        let len = inner.len()?;
+        Ok(CoalescingRaf { inner, threshold, pending: BTreeMap::new(), pending_bytes: 0, len })
+    }
+
+    /// The number of bytes waiting to be written to the inner file.
+    pub fn dirty_bytes(&self) -> u64 {
+        self.pending_bytes
+    }
+
+    /// The inner file. Pending writes have not reached it yet.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Writes all pending data to the inner file, without flushing it.
+    pub fn write_back(&mut self) -> Result<(), RafError> {
+        while let Some((&at, _)) = self.pending.iter().next() {
+            let run = self.pending.remove(&at).expect("run was just found");
+            if let Err(e) = self.inner.write_all_at(at, &run) {
+                self.pending.insert(at, run);
+                return Err(e);
+            }
+            self.pending_bytes -= run.len() as u64;
+        }
+        Ok(())
+    }
+
+    // Adds a write to the pending runs, merging it with every run it overlaps or touches.
+    fn insert(&mut self, at: u64, data: &[u8]) {
+        let end = at + data.len() as u64;
+        let mut start = at;
+        let mut merged_end = end;
+        let touching: Vec<u64> = self.pending.range(..=end).rev()
+            .take_while(|&(&run_at, run)| run_at + run.len() as u64 >= at)
+            .map(|(&run_at, _)| run_at)
+            .collect();
+        // A write into or right after a single run, the common case of a sequential writer, goes
+        // into that run's buffer instead of a new one.
+        if let [run_at] = touching[..] {
+            if run_at <= at {
+                let run = self.pending.get_mut(&run_at).expect("run was just found");
+                let offset = (at - run_at) as usize;
+                let overlap = cmp::min(run.len() - offset, data.len());
+                run[offset..offset + overlap].copy_from_slice(&data[..overlap]);
+                run.extend_from_slice(&data[overlap..]);
+                self.pending_bytes += (data.len() - overlap) as u64;
+                return;
+            }
+        }
+        let mut runs = Vec::with_capacity(touching.len());
+        for run_at in touching {
+            let run = self.pending.remove(&run_at).expect("run was just found");
+            self.pending_bytes -= run.len() as u64;
+            start = cmp::min(start, run_at);
+            merged_end = cmp::max(merged_end, run_at + run.len() as u64);
+            runs.push((run_at, run));
+        }
+        let mut buf = vec![0u8; (merged_end - start) as usize];
+        for (run_at, run) in runs {
+            let offset = (run_at - start) as usize;
+            buf[offset..offset + run.len()].copy_from_slice(&run);
+        }
+        let offset = (at - start) as usize;
+        buf[offset..offset + data.len()].copy_from_slice(data);
+        self.pending_bytes += buf.len() as u64;
+        self.pending.insert(start, buf);
+    }
+}
+
+impl<R: RandomAccessFile> Drop for CoalescingRaf<R> {
+    fn drop(&mut self) {
+        let _ = self.write_back();
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for CoalescingRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let n = cmp::min(self.len.saturating_sub(at), dat.len() as u64) as usize;
+        let end = at + n as u64;
+        let mut filled = 0;
+        while filled < n {
+            match self.inner.read_at(at + filled as u64, &mut dat[filled..n]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        // Past the end of the inner file, which pending writes may have extended, the file
+        // reads as zeros except where pending data covers it.
+        for b in &mut dat[filled..n] {
+            *b = 0;
+        }
+        for (&run_at, run) in self.pending.range(..end).rev() {
+            let run_end = run_at + run.len() as u64;
+            if run_end <= at {
+                break;
+            }
+            let from = cmp::max(run_at, at);
+            let to = cmp::min(run_end, end);
+            dat[(from - at) as usize..(to - at) as usize]
+                .copy_from_slice(&run[(from - run_at) as usize..(to - run_at) as usize]);
+        }
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let end = match at.checked_add(data.len() as u64) {
+            Some(end) => end,
+            None => return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the address space").into())
+        };
+        if data.is_empty() {
+            return Ok(0);
+        }
+        self.insert(at, data);
+        self.len = cmp::max(self.len, end);
+        if self.pending_bytes > self.threshold as u64 {
+            self.write_back()?;
+        }
+        Ok(data.len())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.len;
+        self.write_all_at(at, data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(self.len)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.set_len(len)?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Writes all pending data and flushes the inner file.
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.sync_all()
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata { len: self.len, ..self.inner.metadata()? })
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.write_back()?;
+        self.inner.punch_hole(at, len)
+    }
+}
+
+impl<R: RandomAccessFile> WriteBack for CoalescingRaf<R> {
+    fn dirty_bytes(&self) -> u64 {
+        CoalescingRaf::dirty_bytes(self)
+    }
+
+    fn write_back(&mut self) -> Result<(), RafError> {
+        CoalescingRaf::write_back(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coalesce::CoalescingRaf;
+    use test_util::Counting;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+
+    #[test]
+    fn merges_adjacent_and_overlapping_writes() {
+        let inner = Counting::new(MemoryRandomAccessFile::from_vec(b"0123456789".to_vec()));
+        let mut raf = CoalescingRaf::new(inner, 64).unwrap();
+        for (i, b) in b"abcdef".iter().enumerate() {
+            raf.write_all_at(4 + i as u64, &[*b]).unwrap();
+        }
+        raf.write_all_at(2, b"XYZ").unwrap();
+        raf.write_all_at(14, b"!").unwrap();
+        assert_eq!(raf.dirty_bytes(), 9);
+        assert_eq!(raf.len().unwrap(), 15);
+
+        let mut buf = [0xffu8; 16];
+        assert_eq!(raf.read_at(0, &mut buf).unwrap(), 15);
+        assert_eq!(&buf[..15], b"01XYZbcdef\0\0\0\0!");
+        assert_eq!(raf.get_ref().writes, 0);
+
+        raf.flush().unwrap();
+        assert_eq!(raf.get_ref().writes, 2);
+        assert_eq!(raf.get_ref().inner.as_slice(), &buf[..15]);
+
+        raf.write_all_at(0, &[7u8; 65]).unwrap();
+        assert_eq!((raf.dirty_bytes(), raf.get_ref().writes), (0, 3));
+    }
+
+    #[test]
+    fn extends_a_run_in_place() {
+        let mut raf = CoalescingRaf::new(Counting::new(MemoryRandomAccessFile::new()), 1 << 20).unwrap();
+        for i in 0..1000u32 {
+            raf.write_all_at(i as u64, &[i as u8]).unwrap();
+        }
+        raf.write_all_at(998, b"end").unwrap();
+        raf.write_all_at(10, b"mid").unwrap();
+        assert_eq!((raf.dirty_bytes(), raf.pending.len()), (1001, 1));
+
+        raf.flush().unwrap();
+        let data = raf.get_ref().inner.as_slice();
+        assert_eq!((data.len(), &data[10..13], &data[998..]), (1001, &b"mid"[..], &b"end"[..]));
+        assert_eq!(raf.get_ref().writes, 1);
+    }
+}
@@ -0,0 +1,125 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The error type of `RandomAccessFile` and `OpenRandomAccessFile`. The crate specific failures
+/// get their own variants so they can be told apart from each other and from operating system
+/// errors, which are carried by `Io` together with the path involved when it is known.
+///
+/// `RafError` converts to and from `std::io::Error`, so `?` works in either direction. Converting
+/// a crate specific variant to an `io::Error` wraps it, and converting that `io::Error` back
+/// recovers the original variant; this is how `Serialize` implementations, which are bound to
+/// `io::Error`, report a `CorruptLength`.
+#[derive(Debug)]
+pub enum RafError {
+    /// The end of the file was reached after `read` of the `expected` bytes starting at `offset`.
+    ShortRead { offset: u64, expected: usize, read: usize },
+    /// A single byte was requested at `offset`, which is at or past the end of the file.
+    PastEof { offset: u64 },
+    /// A length prefix promised `len` elements, more than the data that follows it holds or than
+    /// can be allocated.
+    CorruptLength { len: u64 },
+    /// A `RangeWriter` was asked to write past the end of its `limit` byte slot at `offset`.
+    SlotOverflow { offset: u64, limit: u64 },
+    /// An error from the operating system or the underlying reader or writer.
+    Io { source: io::Error, path: Option<PathBuf> },
+}
+
+impl RafError {
+    /// The closest `io::ErrorKind`, for code that only cares about the broad category.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            RafError::ShortRead { .. } | RafError::PastEof { .. } => ErrorKind::UnexpectedEof,
+            RafError::CorruptLength { .. } => ErrorKind::InvalidData,
+            RafError::SlotOverflow { .. } => ErrorKind::InvalidInput,
+            RafError::Io { ref source, .. } => source.kind(),
+        }
+    }
+
+    /// The path of the file the error happened on, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            RafError::Io { ref path, .. } => path.as_ref().map(|p| p.as_path()),
+            _ => None
+        }
+    }
+
+    /// Attaches `path` to an `Io` error that doesn't have one yet.
+    pub fn with_path<P: AsRef<Path>>(self, path: P) -> RafError {
+        match self {
+            RafError::Io { source, path: None } => RafError::Io { source, path: Some(path.as_ref().to_path_buf()) },
+            e => e
+        }
+    }
+}
+
+impl fmt::Display for RafError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RafError::ShortRead { offset, expected, read } =>
+                write!(f, "short read at offset {}: expected {} bytes, got {}", offset, expected, read),
+            RafError::PastEof { offset } => write!(f, "offset {} is past the end of the file", offset),
+            RafError::CorruptLength { len } => write!(f, "corrupt length prefix {}", len),
+            RafError::SlotOverflow { offset, limit } =>
+                write!(f, "value does not fit in the {} byte slot at offset {}", limit, offset),
+            RafError::Io { ref source, path: Some(ref path) } => write!(f, "{}: {}", path.display(), source),
+            RafError::Io { ref source, path: None } => source.fmt(f),
+        }
+    }
+}
+
+impl error::Error for RafError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RafError::Io { ref source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for RafError {
+    fn from(e: io::Error) -> RafError {
+        if !e.get_ref().is_some_and(|inner| inner.is::<RafError>()) {
+            return RafError::Io { source: e, path: None };
+        }
+        match e.into_inner().map(|inner| inner.downcast::<RafError>()) {
+            Some(Ok(inner)) => *inner,
+            _ => unreachable!()
+        }
+    }
+}
+
+impl From<RafError> for io::Error {
+    fn from(e: RafError) -> io::Error {
+        match e {
+            RafError::Io { source, path: None } => source,
+            e => io::Error::new(e.kind(), e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::RafError;
+    use std::io;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn round_trips_through_io_error() {
+        let e: io::Error = RafError::ShortRead { offset: 4, expected: 8, read: 3 }.into();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        match RafError::from(e) {
+            RafError::ShortRead { offset: 4, expected: 8, read: 3 } => (),
+            other => panic!("unexpected {:?}", other)
+        }
+
+        let e = RafError::from(io::Error::new(ErrorKind::NotFound, "gone")).with_path("a.bin");
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert_eq!(e.to_string(), "a.bin: gone");
+        let plain: io::Error = RafError::from(io::Error::new(ErrorKind::NotFound, "x")).into();
+        assert!(plain.get_ref().is_some_and(|inner| !inner.is::<RafError>()));
+    }
+}
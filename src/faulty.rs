@@ -0,0 +1,203 @@
+use std::cmp;
+use std::io::Error;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+fn fault() -> RafError {
+    Error::other("injected fault").into()
+}
+
+/// A wrapper for tests that makes another backend misbehave on demand, to exercise the handling
+/// of short reads, failed writes, corruption and crashes in code built on this crate. Nothing is
+/// injected until programmed:
+///
+/// - `set_max_read` makes reads return at most that many bytes, as reads may.
+/// - `fail_nth_write` fails a single later write or append.
+/// - `corrupt_byte` flips bits of a byte whenever it is read; the file itself is left alone.
+/// - `set_write_budget` lets that many more bytes be written, then fails every write and append,
+///   like a disk that died. The write that runs out of budget is torn: the part that fits is
+///   written, and the rest is not.
+///
+/// Injected errors have `ErrorKind::Other`.
+#[derive(Debug)]
+pub struct FaultyRandomAccessFile<R: RandomAccessFile> {
+    inner: R,
+    max_read: Option<usize>,
+    // Writes to go until the failing one, counting it.
+    fail_write: Option<u64>,
+    corrupt: Vec<(u64, u8)>,
+    write_budget: Option<u64>,
+}
+
+impl<R: RandomAccessFile> FaultyRandomAccessFile<R> {
+    pub fn new(inner: R) -> FaultyRandomAccessFile<R> {
+        FaultyRandomAccessFile { inner, max_read: None, fail_write: None, corrupt: Vec::new(), write_budget: None }
+    }
+
+    /// Caps every read at `max` bytes, `None` removing the cap. Panics on `Some(0)`, which would
+    /// look like the end of the file.
+    pub fn set_max_read(&mut self, max: Option<usize>) {
+        assert!(max != Some(0), "reads must be allowed at least one byte");
+        self.max_read = max;
+    }
+
+    /// Fails the `n`th write or append from now, 1 being the next one. Panics if `n` is 0.
+    pub fn fail_nth_write(&mut self, n: u64) {
+        assert!(n != 0, "writes are counted from 1");
+        self.fail_write = Some(n);
+    }
+
+    /// XORs the byte at `at` with `mask` whenever it is read.
+    pub fn corrupt_byte(&mut self, at: u64, mask: u8) {
+        self.corrupt.push((at, mask));
+    }
+
+    /// Lets `budget` more bytes be written before writes start failing, `None` removing the limit.
+    pub fn set_write_budget(&mut self, budget: Option<u64>) {
+        self.write_budget = budget;
+    }
+
+    /// Stops injecting any fault.
+    pub fn clear_faults(&mut self) {
+        self.max_read = None;
+        self.fail_write = None;
+        self.corrupt.clear();
+        self.write_budget = None;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // How many of `len` bytes a write may go through with.
+    fn admit_write(&mut self, len: usize) -> Result<usize, RafError> {
+        if let Some(n) = self.fail_write {
+            self.fail_write = if n == 1 { None } else { Some(n - 1) };
+            if n == 1 {
+                return Err(fault());
+            }
+        }
+        match self.write_budget {
+            Some(0) => Err(fault()),
+            Some(budget) => {
+                let allowed = cmp::min(budget, len as u64);
+                self.write_budget = Some(budget - allowed);
+                Ok(allowed as usize)
+            },
+            None => Ok(len)
+        }
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for FaultyRandomAccessFile<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let len = self.max_read.map_or(dat.len(), |max| cmp::min(max, dat.len()));
+        let n = self.inner.read_at(at, &mut dat[..len])?;
+        for &(pos, mask) in &self.corrupt {
+            if pos >= at && pos < at + n as u64 {
+                dat[(pos - at) as usize] ^= mask;
+            }
+        }
+        Ok(n)
+    }
+
+    /// A write that runs out of budget part way returns how much it wrote, like any short write.
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let allowed = self.admit_write(data.len())?;
+        self.inner.write_at(at, &data[..allowed])
+    }
+
+    /// An append that runs out of budget part way writes what fits and fails.
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let allowed = self.admit_write(data.len())?;
+        let at = self.inner.append(&data[..allowed])?;
+        if allowed < data.len() {
+            return Err(fault());
+        }
+        Ok(at)
+    }
+
+    /// Passed through without injecting any fault.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use faulty::FaultyRandomAccessFile;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn injects_programmed_faults() {
+        let mut raf = FaultyRandomAccessFile::new(MemoryRandomAccessFile::new());
+        raf.fail_nth_write(2);
+        raf.append(b"faulty").unwrap();
+        assert_eq!(raf.write_at(0, b"F").unwrap_err().kind(), ErrorKind::Other);
+        raf.write_all_at(0, b"F").unwrap();
+
+        let mut buf = [0u8; 6];
+        raf.set_max_read(Some(4));
+        assert_eq!(raf.read_at(0, &mut buf).unwrap(), 4);
+        raf.corrupt_byte(5, 0x20);
+        raf.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(&buf, b"FaultY");
+
+        raf.set_write_budget(Some(3));
+        assert!(raf.append(b"12345").is_err());
+        assert!(raf.write_at(0, b"f").is_err());
+        raf.clear_faults();
+        let mut buf = [0u8; 9];
+        raf.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(&buf, b"Faulty123");
+        assert!(raf.compare_and_write_at(6, b"123", b"!!!").unwrap());
+    }
+}
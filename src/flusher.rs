@@ -0,0 +1,277 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// A `RandomAccessFile` that holds written data in memory before it reaches the file below it,
+/// like `BufRandomAccessFile` and `CoalescingRaf`.
+pub trait WriteBack : RandomAccessFile {
+    /// The number of bytes written but not yet passed on to the inner file.
+    fn dirty_bytes(&self) -> u64;
+    /// Passes all held data on to the inner file, without flushing it.
+    fn write_back(&mut self) -> Result<(), RafError>;
+}
+
+/// Controls when `FlushingRaf` flushes in the background.
+#[derive(Debug, Clone)]
+pub struct FlushPolicy {
+    /// Flush this often while there is dirty data. `None` means only on the threshold.
+    pub interval: Option<Duration>,
+    /// Flush as soon as this many bytes are dirty. `None` means only on the interval.
+    pub dirty_threshold: Option<u64>,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> FlushPolicy {
+        FlushPolicy { interval: Some(Duration::from_secs(1)), dirty_threshold: None }
+    }
+}
+
+struct State<R> {
+    raf: R,
+    // The first error of a background flush, reported by the next call.
+    error: Option<RafError>,
+    stop: bool,
+}
+
+struct Shared<R> {
+    state: Mutex<State<R>>,
+    wake: Condvar,
+}
+
+fn lock<R>(shared: &Shared<R>) -> MutexGuard<'_, State<R>> {
+    shared.state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A wrapper around a `WriteBack` file that flushes it from a background thread, either every
+/// `interval` or once `dirty_threshold` bytes are dirty, bounding how much written data a crash
+/// can lose without syncing on every write. Flushing here means `flush`: the data reaches the
+/// operating system, but is not synced to disk.
+///
+/// An error from a background flush is returned by the next call on the handle, and the thread
+/// doesn't try again until it has been. `shutdown` stops the thread and flushes whatever is left;
+/// dropping the handle does the same, ignoring errors.
+pub struct FlushingRaf<R: WriteBack + Send + 'static> {
+    shared: Arc<Shared<R>>,
+    dirty_threshold: Option<u64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<R: WriteBack + Send + 'static> FlushingRaf<R> {
+    pub fn new(inner: R, policy: FlushPolicy) -> Result<FlushingRaf<R>, RafError> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { raf: inner, error: None, stop: false }),
+            wake: Condvar::new(),
+        });
+        let background = shared.clone();
+        let (interval, threshold) = (policy.interval, policy.dirty_threshold);
+        let thread = thread::Builder::new().name("raf-flusher".to_string()).spawn(move || {
+            let mut state = lock(&background);
+            loop {
+                // Checked before waiting too, since a wakeup sent while the thread wasn't waiting
+                // is lost.
+                if state.stop {
+                    return;
+                }
+                // A failed flush leaves the data dirty, so while its error is waiting to be reported
+                // the thread waits instead of flushing again, which would never let go of the lock.
+                let due = state.error.is_none()
+                    && threshold.is_some_and(|threshold| state.raf.dirty_bytes() >= threshold);
+                if !due {
+                    state = match interval {
+                        Some(interval) => background.wake.wait_timeout(state, interval)
+                            .unwrap_or_else(|e| e.into_inner()).0,
+                        None => background.wake.wait(state).unwrap_or_else(|e| e.into_inner())
+                    };
+                    if state.stop {
+                        return;
+                    }
+                }
+                if state.error.is_none() && state.raf.dirty_bytes() > 0 {
+                    if let Err(e) = state.raf.flush() {
+                        state.error = Some(e);
+                    }
+                }
+            }
+        })?;
+        Ok(FlushingRaf { shared, dirty_threshold: policy.dirty_threshold, thread: Some(thread) })
+    }
+
+    /// The number of dirty bytes the background thread has yet to flush.
+    pub fn dirty_bytes(&self) -> u64 {
+        lock(&self.shared).raf.dirty_bytes()
+    }
+
+    /// Stops the background thread and flushes all remaining data.
+    pub fn shutdown(mut self) -> Result<(), RafError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), RafError> {
+        if let Some(thread) = self.thread.take() {
+            lock(&self.shared).stop = true;
+            self.shared.wake.notify_one();
+            let _ = thread.join();
+        }
+        let mut state = lock(&self.shared);
+        match state.error.take() {
+            Some(e) => Err(e),
+            None => state.raf.flush()
+        }
+    }
+
+    fn with<T, F>(&mut self, op: F) -> Result<T, RafError> where F: FnOnce(&mut R) -> Result<T, RafError> {
+        let mut state = lock(&self.shared);
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        let result = op(&mut state.raf);
+        if self.dirty_threshold.is_some_and(|threshold| state.raf.dirty_bytes() >= threshold) {
+            self.shared.wake.notify_one();
+        }
+        result
+    }
+}
+
+impl<R: WriteBack + Send + 'static> Drop for FlushingRaf<R> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl<R: WriteBack + Send + 'static> fmt::Debug for FlushingRaf<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FlushingRaf").field("dirty_threshold", &self.dirty_threshold).finish()
+    }
+}
+
+impl<R: WriteBack + Send + 'static> RandomAccessFile for FlushingRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.with(|raf| raf.read_at(at, dat))
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.with(|raf| raf.write_at(at, data))
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.with(|raf| raf.append(data))
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.with(|raf| raf.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.with(|raf| raf.set_len(len))
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.with(|raf| raf.flush())
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.with(|raf| raf.sync_data())
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.with(|raf| raf.sync_all())
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.with(|raf| raf.metadata())
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.with(|raf| raf.advise(at, len, advice))
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.with(|raf| raf.punch_hole(at, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flusher::{FlushPolicy, FlushingRaf, WriteBack};
+    use BufRandomAccessFile;
+    use CoalescingRaf;
+    use FaultyRandomAccessFile;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::thread;
+    use std::time::Duration;
+
+    fn wait_until_clean<R: WriteBack + Send + 'static>(raf: &FlushingRaf<R>) {
+        for _ in 0..500 {
+            if raf.dirty_bytes() == 0 {
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("the background thread never flushed");
+    }
+
+    #[test]
+    fn flushes_on_threshold_interval_and_shutdown() {
+        let policy = FlushPolicy { interval: None, dirty_threshold: Some(16) };
+        let inner = CoalescingRaf::new(MemoryRandomAccessFile::new(), 1 << 20).unwrap();
+        let mut raf = FlushingRaf::new(inner, policy).unwrap();
+        raf.write_all_at(0, &[1; 8]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(raf.dirty_bytes(), 8);
+        raf.write_all_at(8, &[2; 10]).unwrap();
+        wait_until_clean(&raf);
+
+        let policy = FlushPolicy { interval: Some(Duration::from_millis(5)), dirty_threshold: None };
+        let inner = BufRandomAccessFile::new(MemoryRandomAccessFile::new()).unwrap();
+        let mut raf = FlushingRaf::new(inner, policy).unwrap();
+        raf.append(b"ticks").unwrap();
+        wait_until_clean(&raf);
+
+        let path = env::temp_dir().join("raf_flusher.bin");
+        let _ = fs::remove_file(&path);
+        let file: File = OpenRandomAccessFile::new(&path).unwrap();
+        let policy = FlushPolicy { interval: None, dirty_threshold: None };
+        let mut raf = FlushingRaf::new(CoalescingRaf::new(file, 1 << 20).unwrap(), policy).unwrap();
+        raf.append(b"drained").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"");
+        raf.shutdown().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"drained");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn failed_flush_is_reported_without_spinning() {
+        let mut faulty = FaultyRandomAccessFile::new(MemoryRandomAccessFile::new());
+        faulty.set_write_budget(Some(0));
+        let policy = FlushPolicy { interval: None, dirty_threshold: Some(4) };
+        let mut raf = FlushingRaf::new(CoalescingRaf::new(faulty, 1 << 20).unwrap(), policy).unwrap();
+        raf.write_all_at(0, &[1; 8]).unwrap();
+
+        // The data stays dirty past the threshold, yet the handle stays usable.
+        let mut reported = false;
+        for _ in 0..500 {
+            assert_eq!(raf.dirty_bytes(), 8);
+            if raf.len().is_err() {
+                reported = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(reported, "the background flush error was never reported");
+        assert!(raf.shutdown().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use checksum::Crc32;
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use OpenRandomAccessFile;
+use RafError;
+use RandomAccessFile;
+use Serialize;
+
+static MARKER_SIZE: usize = 8 + 8 + 4;
+
+// A progress marker: a sequence number, the committed length of the data file, and a CRC-32 of
+// both. Markers alternate between two slots so a torn write can only destroy the newer one.
+fn encode_marker(seq: u64, committed: u64) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::with_capacity(MARKER_SIZE);
+    seq.serialize(&mut buf)?;
+    committed.serialize(&mut buf)?;
+    let mut crc = Crc32::new();
+    crc.update(&buf);
+    crc.finish().serialize(&mut buf)?;
+    Ok(buf)
+}
+
+fn decode_marker(buf: &[u8]) -> Option<(u64, u64)> {
+    let mut crc = Crc32::new();
+    crc.update(&buf[..16]);
+    let mut from = buf;
+    let seq = u64::deserialize(&mut from).ok()?;
+    let committed = u64::deserialize(&mut from).ok()?;
+    let stored = u32::deserialize(&mut from).ok()?;
+    if stored == crc.finish() { Some((seq, committed)) } else { None }
+}
+
+/// A long running append to a data file whose progress is durably recorded in a side file, so
+/// that after a crash the session can be resumed from the last `checkpoint` instead of restarting.
+///
+/// Opening a session whose progress file holds a marker truncates the data file back to the
+/// committed length, discarding the partial tail written after the last checkpoint. Opening a
+/// session without a progress file treats the current contents of the data file as committed.
+/// Both files are opened as backends of type `R`.
+#[derive(Debug)]
+pub struct IngestSession<R: RandomAccessFile> {
+    data: R,
+    progress: R,
+    progress_path: PathBuf,
+    seq: u64,
+    committed: u64,
+    len: u64,
+}
+
+impl<R: OpenRandomAccessFile> IngestSession<R> {
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(data_path: P, progress_path: Q) -> Result<IngestSession<R>, RafError> {
+        let progress_path = progress_path.as_ref().to_path_buf();
+        let mut data = R::new(data_path)?;
+        let mut progress = R::new(&progress_path)?;
+
+        let mut latest = None;
+        if progress.len()? >= 2 * MARKER_SIZE as u64 {
+            let mut buf = vec![0u8; 2 * MARKER_SIZE];
+            progress.read_exact_at(0, &mut buf)?;
+            for slot in buf.chunks(MARKER_SIZE) {
+                if let Some((seq, committed)) = decode_marker(slot) {
+                    if latest.is_none_or(|(s, _)| seq > s) {
+                        latest = Some((seq, committed));
+                    }
+                }
+            }
+            // All zeros means a previous open crashed before writing its first marker.
+            if latest.is_none() && buf.iter().any(|&b| b != 0) {
+                return Err(Error::new(ErrorKind::InvalidData, "no valid progress marker in the progress file").into());
+            }
+        }
+
+        let session = match latest {
+            Some((seq, committed)) => {
+                if data.len()? < committed {
+                    return Err(Error::new(ErrorKind::InvalidData, "data file is shorter than its committed length").into());
+                }
+                data.set_len(committed)?;
+                data.sync_all()?;
+                IngestSession { data, progress, progress_path, seq, committed, len: committed }
+            },
+            None => {
+                let len = data.len()?;
+                progress.set_len(2 * MARKER_SIZE as u64)?;
+                let mut session = IngestSession {
+                    data, progress, progress_path, seq: 0, committed: 0, len
+                };
+                session.checkpoint()?;
+                session
+            }
+        };
+        Ok(session)
+    }
+}
+
+impl<R: RandomAccessFile> IngestSession<R> {
+    /// Appends `data` to the data file. It only becomes durable at the next `checkpoint`.
+    pub fn append(&mut self, data: &[u8]) -> Result<(), RafError> {
+        self.data.write_all_at(self.len, data)?;
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Syncs everything appended so far and records it as committed. Returns the committed length.
+    pub fn checkpoint(&mut self) -> Result<u64, RafError> {
+        self.data.sync_data()?;
+        let seq = self.seq + 1;
+        let marker = encode_marker(seq, self.len)?;
+        self.progress.write_all_at((seq % 2) * MARKER_SIZE as u64, &marker)?;
+        self.progress.sync_data()?;
+        self.seq = seq;
+        self.committed = self.len;
+        Ok(self.committed)
+    }
+
+    /// The length of the data file as of the last checkpoint.
+    pub fn committed(&self) -> u64 {
+        self.committed
+    }
+
+    /// The length of the data file including data appended since the last checkpoint.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Checkpoints, removes the progress file and returns the data file.
+    pub fn finish(mut self) -> Result<R, RafError> {
+        self.checkpoint()?;
+        fs::remove_file(&self.progress_path)?;
+        Ok(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ingest::IngestSession;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+
+    #[test]
+    fn resume_after_crash() {
+        let data = env::temp_dir().join("raf_ingest.bin");
+        let progress = env::temp_dir().join("raf_ingest.progress");
+        let (data, progress) = (data.to_str().unwrap(), progress.to_str().unwrap());
+        let _ = fs::remove_file(data);
+        let _ = fs::remove_file(progress);
+
+        {
+            let mut session = IngestSession::<File>::open(data, progress).unwrap();
+            session.append(b"first ").unwrap();
+            assert_eq!(session.checkpoint().unwrap(), 6);
+            session.append(b"second ").unwrap();
+            assert_eq!(session.checkpoint().unwrap(), 13);
+            session.append(b"lost").unwrap();
+            // Dropped without a checkpoint, as if the process crashed.
+        }
+        assert_eq!(fs::metadata(data).unwrap().len(), 17);
+
+        let mut session = IngestSession::<File>::open(data, progress).unwrap();
+        assert_eq!(session.committed(), 13);
+        session.append(b"third").unwrap();
+        session.finish().unwrap();
+        assert_eq!(fs::read(data).unwrap(), b"first second third");
+        assert!(fs::metadata(progress).is_err());
+        let _ = fs::remove_file(data);
+    }
+}
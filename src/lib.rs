@@ -17,17 +17,108 @@ IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMA
 WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE
 SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 */
+#[cfg(feature = "cfile")]
 extern crate cfile_rs;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
 
+use std::cmp;
+use std::fs::File;
 use std::io::Error;
-use cfile_rs::CFile;
-use std::io::SeekFrom;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
 use std::io::Write;
-use std::io::Seek;
 use std::slice;
 use std::io::Read;
-use std::path::Path;
 use std::mem;
+use std::path::Path;
+use checksum::Crc32;
+
+pub mod backend;
+
+mod advice;
+mod append_only;
+mod async_raf;
+mod blob;
+mod buffered;
+mod chain;
+mod checksum;
+mod chunks;
+mod coalesce;
+mod error;
+mod faulty;
+mod flusher;
+mod ingest;
+mod lock;
+mod metadata;
+mod observe;
+mod offset;
+mod pool;
+mod options;
+mod raf_slice;
+mod range;
+mod read_ahead;
+mod read_only;
+mod record;
+mod registry;
+mod retry;
+mod scatter;
+mod seal;
+mod seekable;
+mod shared;
+#[cfg(any(unix, windows))]
+mod shared_raf;
+mod stats;
+#[cfg(test)]
+mod test_util;
+mod throttle;
+mod timeout;
+
+pub use advice::Advice;
+pub use append_only::AppendOnly;
+pub use async_raf::{AsyncRaf, AsyncRandomAccessFile, RafFuture, Spawner};
+pub use blob::{begin_blob, open_blob, BlobId, BlobReader, BlobWriter};
+pub use buffered::BufRandomAccessFile;
+pub use chain::{Chain, ChainIter, Node, NodeOffset};
+pub use chunks::Chunks;
+pub use coalesce::CoalescingRaf;
+pub use error::RafError;
+pub use faulty::FaultyRandomAccessFile;
+pub use flusher::{FlushPolicy, FlushingRaf, WriteBack};
+pub use ingest::IngestSession;
+pub use lock::LockedRaf;
+pub use metadata::{RafMetadata, StorageKind};
+#[cfg(unix)]
+pub use backend::block_device::BlockDeviceRandomAccessFile;
+pub use backend::direct::DirectRandomAccessFile;
+pub use backend::memory::MemoryRandomAccessFile;
+#[cfg(feature = "mmap")]
+pub use backend::mmap::MmapRandomAccessFile;
+pub use observe::{IoEvent, IoObserver, IoOp, ObservedRaf};
+pub use offset::Offset;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use backend::uring::UringRandomAccessFile;
+pub use options::RafOptions;
+pub use pool::{PooledRaf, RafPool};
+pub use raf_slice::RafSlice;
+pub use range::{RangeReader, RangeWriter};
+pub use read_ahead::ReadAheadRaf;
+pub use read_only::ReadOnlyRaf;
+pub use record::{replay, RecordingRaf};
+pub use registry::{is_open_exclusive, ExclusiveRaf};
+pub use retry::{is_transient, RetryPolicy, RetryingRaf};
+pub use scatter::{serialize_scattered, deserialize_gathered, GatherReader};
+pub use seal::{seal, open_sealed, read_seal_footer, verify_sealed, SealFooter};
+pub use seekable::SeekableRaf;
+pub use shared::{SharedReader, SharedWriter};
+#[cfg(any(unix, windows))]
+pub use shared_raf::SharedRaf;
+pub use stats::{IoStats, StatsRaf};
+pub use throttle::{RateLimiter, ThrottledRaf};
+pub use timeout::TimeoutRaf;
 
 static SIZE_OF_U64: usize = 8;
 static SIZE_OF_U32: usize = 4;
@@ -38,59 +129,401 @@ static SIZE_OF_I32: usize = 4;
 static SIZE_OF_I16: usize = 2;
 static SIZE_OF_I8:  usize = 1;
 
+// Chunk size of the buffered fallbacks of `copy_range` and `fill_at`.
+const COPY_BUFFER_SIZE: u64 = 64 * 1024;
+
 pub trait RandomAccessFile : Sized {
-    fn new(path: &str) -> Result<Self, Error>;
-    fn read_at(&mut self, at: usize, dat: &mut [u8]) -> Result<usize, Error>;
-    fn write_at(&mut self, at: usize, dat: &[u8]) -> Result<usize, Error>;
-    fn append(&mut self, dat: &[u8]) -> Result<(), Error>;
-    fn at(&mut self, index: usize) -> Result<u8, Error> {
+    /// Reads up to `dat.len()` bytes starting at `at` into the front of `dat`, and returns how
+    /// many were read. Fewer bytes than asked for is not an error: a read that reaches the end of
+    /// the file stops there, one that starts at or past it returns 0, and a backend may return
+    /// less for other reasons too; `read_exact_at` retries until `dat` is full. The part of `dat`
+    /// past the returned count is left as it was. On error, nothing is known to have been read.
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError>;
+    /// Writes up to `dat.len()` bytes starting at `at`, extending the file if needed, and returns
+    /// how many were written. A short write stores exactly the first bytes of `dat` it reports and
+    /// none of the rest; `write_all_at` retries until everything is written. On error, any prefix
+    /// of `dat`, including none or all of it, may already have been written.
+    fn write_at(&mut self, at: u64, dat: &[u8]) -> Result<usize, RafError>;
+    /// Writes all of `dat` at the end of the file and returns the offset it was written at, so
+    /// callers can record where each appended record starts. Appending through several handles
+    /// at once is only safe if they coordinate, e.g. with `LockedRaf`.
+    fn append(&mut self, dat: &[u8]) -> Result<u64, RafError>;
+    /// The current length of the file in bytes.
+    fn len(&mut self) -> Result<u64, RafError>;
+    /// Truncates or extends the file to exactly `len` bytes. Extending fills the new space with
+    /// zeros.
+    fn set_len(&mut self, len: u64) -> Result<(), RafError>;
+    fn is_empty(&mut self) -> Result<bool, RafError> {
+        self.len().map(|len| len == 0)
+    }
+    /// Pushes any data buffered by the backend itself (stdio buffers, a memory map's dirty pages)
+    /// down to the operating system. This does not by itself make the data durable.
+    fn flush(&mut self) -> Result<(), RafError>;
+    /// Flushes and then waits until the file's contents have reached the storage device, like
+    /// `File::sync_data`. Metadata that isn't needed to read the data back may not be synced.
+    fn sync_data(&mut self) -> Result<(), RafError>;
+    /// Like `sync_data`, but also syncs all of the file's metadata, like `File::sync_all`.
+    fn sync_all(&mut self) -> Result<(), RafError>;
+    /// Reads the byte at `index`. Fails with `RafError::PastEof` if `index` is at or past the end
+    /// of the file.
+    fn at(&mut self, index: u64) -> Result<u8, RafError> {
         let x = &mut [0u8];
         match self.read_at(index, x) {
+            Ok(0) => Err(RafError::PastEof { offset: index }),
             Ok(_) => Ok(x[0]),
             Err(e) => Err(e)
         }
     }
-}
 
-impl RandomAccessFile for CFile {
-    fn new(path: &str) -> Result<CFile, Error> {
-        CFile::open_random_access(path)
+    /// Reads exactly `dat.len()` bytes starting at `at`, calling `read_at` as many times as
+    /// needed. Fails with `RafError::ShortRead` if the end of the file is reached first; `dat` is
+    /// left partially filled in that case.
+    fn read_exact_at(&mut self, at: u64, dat: &mut [u8]) -> Result<(), RafError> {
+        let mut filled = 0;
+        while filled < dat.len() {
+            match self.read_at(at + filled as u64, &mut dat[filled..]) {
+                Ok(0) => return Err(RafError::ShortRead { offset: at, expected: dat.len(), read: filled }),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads into each of `bufs` in turn, starting at `at`, and returns the total number of bytes
+    /// read. Like `read_at` it may read less than the buffers can hold. The default
+    /// implementation calls `read_at` once per buffer and stops after the first short read;
+    /// backends that can do it in one call (`File` on Unix, with `preadv`) override it.
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        let mut total = 0;
+        for buf in bufs {
+            let n = self.read_at(at + total as u64, buf)?;
+            total += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Writes each of `bufs` in turn, starting at `at`, and returns the total number of bytes
+    /// written. The default implementation calls `write_at` once per buffer and stops after the
+    /// first short write; `File` on Unix overrides it with `pwritev`.
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        let mut total = 0;
+        for buf in bufs {
+            let n = self.write_at(at + total as u64, buf)?;
+            total += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
     }
 
-    fn read_at(&mut self, at: usize, dat: &mut [u8]) -> Result<usize, Error> {
-        let _ = self.seek(SeekFrom::Start(at as u64));
-        self.read(dat)
+    /// Writes all of `data` starting at `at`, calling `write_at` as many times as needed. Fails
+    /// with `WriteZero` if the backend stops accepting data.
+    fn write_all_at(&mut self, at: u64, data: &[u8]) -> Result<(), RafError> {
+        let mut written = 0;
+        while written < data.len() {
+            match self.write_at(at + written as u64, &data[written..]) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer").into()),
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
     }
 
-    fn write_at(&mut self, at: usize, data: &[u8]) -> Result<usize, Error> {
-        let _ = self.seek(SeekFrom::Start(at as u64));
-        self.write(data)
+    /// Appends the slices of `bufs` one after another as a single record, and returns the offset
+    /// it starts at. The default joins them into one buffer for `append`; `File` hands them to the
+    /// operating system as they are.
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        let mut joined = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+        for buf in bufs {
+            joined.extend_from_slice(buf);
+        }
+        self.append(&joined)
     }
 
-    fn append(&mut self, data: &[u8]) -> Result<(), Error> {
-        let _ = self.seek(SeekFrom::End(0));
-        match self.write_all(data) {
-            Ok(()) => {
-                Ok(())
-            },
-            Err(e) => {
-                Err(e)
+    /// An iterator over the file in chunks of `size` bytes, from the start to the end; see
+    /// `Chunks`. Panics if `size` is 0.
+    fn chunks<'a>(&'a mut self, size: usize) -> Chunks<'a, Self> {
+        Chunks::new(self, size)
+    }
+
+    /// A `Read` over the `len` bytes starting at `at`, e.g. to deserialize a value stored at a
+    /// known offset with `Serialize::deserialize`; see `RangeReader`.
+    fn reader_at<'a>(&'a mut self, at: u64, len: u64) -> RangeReader<'a, Self> {
+        RangeReader::new(self, at, len)
+    }
+
+    /// A `Write` starting at `at`, for serializing a value at a known offset with
+    /// `Serialize::serialize`. With a `limit`, writing more than that many bytes fails with
+    /// `RafError::SlotOverflow`; see `RangeWriter`.
+    fn writer_at<'a>(&'a mut self, at: u64, limit: Option<u64>) -> RangeWriter<'a, Self> {
+        RangeWriter::new(self, at, limit)
+    }
+
+    /// The length, modification time, permissions and kind of storage of the file. The default
+    /// takes them from the `File` returned by `as_file`, if any, and otherwise only knows the
+    /// length; the length is always the one `len` reports.
+    ///
+    /// Called on a `&mut File`, this takes precedence over the inherent `File::metadata`; write
+    /// `File::metadata(file)` to get the `std::fs::Metadata` instead.
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        let len = self.len()?;
+        let metadata = match self.as_file() {
+            Some(file) => RafMetadata::from(file.metadata()?),
+            None => RafMetadata { len, modified: None, permissions: None, kind: StorageKind::Other }
+        };
+        Ok(RafMetadata { len, ..metadata })
+    }
+
+    /// Returns a second handle to the same file, e.g. to give each thread its own without reopening
+    /// the path, which may since have been renamed or unlinked. For files the two handles share
+    /// the underlying open file, so data written through one, and a length changed through one, is
+    /// seen by the other; what isn't shared is whatever state the backend keeps itself, such as a
+    /// `Read`/`Seek` cursor or a memory mapping (see the backends for details).
+    ///
+    /// Backends that can't do this fail with `ErrorKind::Unsupported`, which is the default. In
+    /// particular `MemoryRandomAccessFile` doesn't implement it, since a copy of the buffer would
+    /// not be the same file, and neither do wrappers whose guarantees rest on being the only
+    /// handle, like `ExclusiveRaf`, `AppendOnly` and `LockedRaf`.
+    fn try_clone(&self) -> Result<Self, RafError> {
+        Err(Error::new(ErrorKind::Unsupported, "try_clone is not supported by this backend").into())
+    }
+
+    /// The `File` behind this backend, if there is one that reads and writes can go to directly.
+    /// Used by `copy_range` to hand the copy to the kernel. Wrappers that check or transform
+    /// writes, like `AppendOnly`, must not expose their inner file here.
+    fn as_file(&self) -> Option<&File> {
+        None
+    }
+
+    /// Copies up to `len` bytes starting at `src_off` in this file to `dst_off` in `dst`, and
+    /// returns the number of bytes copied, which is less than `len` only if the end of this file
+    /// was reached. When both sides are plain files on Linux the copy is done by
+    /// `copy_file_range`, without the data passing through user space; otherwise, or if the
+    /// kernel refuses (e.g. across file systems), it falls back to a buffered loop.
+    fn copy_range<D: RandomAccessFile>(&mut self, src_off: u64, dst: &mut D, dst_off: u64, len: u64)
+        -> Result<u64, RafError> {
+        #[cfg(target_os = "linux")]
+        {
+            if let (Some(src), Some(dst)) = (self.as_file(), dst.as_file()) {
+                if let Some(copied) = backend::std_file::copy_file_range(src, src_off, dst, dst_off, len)? {
+                    return Ok(copied);
+                }
+            }
+        }
+        let mut buf = vec![0u8; cmp::min(len, COPY_BUFFER_SIZE) as usize];
+        let mut copied = 0u64;
+        while copied < len {
+            let want = cmp::min(buf.len() as u64, len - copied) as usize;
+            let read = match self.read_at(src_off + copied, &mut buf[..want]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            };
+            dst.write_all_at(dst_off + copied, &buf[..read])?;
+            copied += read as u64;
+        }
+        Ok(copied)
+    }
+
+    /// The CRC-32 (the zlib/PNG variant, as used by `seal`) of the `len` bytes starting at `at`,
+    /// read in fixed-size chunks so the range can be arbitrarily large. Fails with
+    /// `RafError::ShortRead` if the file ends before the range does.
+    fn checksum_range(&mut self, at: u64, len: u64) -> Result<u32, RafError> {
+        let mut crc = Crc32::new();
+        let mut buf = vec![0u8; cmp::min(len, COPY_BUFFER_SIZE) as usize];
+        let mut done = 0u64;
+        while done < len {
+            let want = cmp::min(buf.len() as u64, len - done) as usize;
+            let read = match self.read_at(at + done, &mut buf[..want]) {
+                Ok(0) => return Err(RafError::ShortRead {
+                    offset: at,
+                    expected: cmp::min(len, usize::MAX as u64) as usize,
+                    read: done as usize,
+                }),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            };
+            crc.update(&buf[..read]);
+            done += read as u64;
+        }
+        Ok(crc.finish())
+    }
+
+    /// Returns whether the CRC-32 of the `len` bytes starting at `at` is `expected`; see
+    /// `checksum_range`.
+    fn verify_range(&mut self, at: u64, len: u64, expected: u32) -> Result<bool, RafError> {
+        Ok(self.checksum_range(at, len)? == expected)
+    }
+
+    /// Writes `new` at `at` if, and only if, the bytes there are currently `expected`, and returns
+    /// whether it did; a range extending past the end of the file never matches. `expected` and
+    /// `new` must be the same length, and are meant to be small records.
+    ///
+    /// For a backend with a file (see `as_file`), and `MmapRandomAccessFile`, the read, comparison
+    /// and write happen under an exclusive advisory lock on the file, so they are atomic with
+    /// respect to other processes doing the same, or holding a `LockedRaf`. A `LockedRaf` must
+    /// hold an exclusive lock itself. `MemoryRandomAccessFile` is only reachable through this
+    /// handle, which `&mut self` already makes exclusive.
+    ///
+    /// The default fails with `ErrorKind::Unsupported` for a backend without a file, since nothing
+    /// keeps another handle to the same storage from writing between the read and the write.
+    /// Wrappers that pass writes through unchanged forward this to the backend they wrap.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        match self.as_file() {
+            Some(file) => file.lock()?,
+            None => return Err(Error::new(ErrorKind::Unsupported, "compare_and_write_at is not supported by this backend").into())
+        }
+        let result = lock::compare_and_write(self, at, expected, new);
+        if let Some(file) = self.as_file() {
+            file.unlock()?;
+        }
+        result
+    }
+
+    /// Makes the file at least `len` bytes long, reserving the storage for it up front so later
+    /// writes into that space don't fragment the file or fail for lack of space. `File` on Linux
+    /// uses `posix_fallocate`; the default, used elsewhere, only extends the file with `set_len`,
+    /// which may leave it sparse. A file that is already long enough is left alone.
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        if self.len()? < len {
+            self.set_len(len)?;
+        }
+        Ok(())
+    }
+
+    /// Tells the backend how `len` bytes starting at `at` are going to be accessed; a `len` of 0
+    /// means up to the end of the file. `File` on Linux passes this on with `posix_fadvise`, and
+    /// `MmapRandomAccessFile` with `madvise`; the default ignores it.
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        let _ = (at, len, advice);
+        Ok(())
+    }
+
+    /// Deallocates the storage of `len` bytes starting at `at`, turning them into a hole that
+    /// reads back as zeros without changing the length of the file. Backends that can't do this
+    /// fail with `ErrorKind::Unsupported`, which is the default; `File` supports it on Linux
+    /// (`fallocate(FALLOC_FL_PUNCH_HOLE)`) if the file system does.
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        let _ = (at, len);
+        Err(Error::new(ErrorKind::Unsupported, "punch_hole is not supported by this backend").into())
+    }
+
+    /// Sets `len` bytes starting at `at` to `byte`, extending the file if needed. The data is
+    /// written from a small fixed-size buffer, so `len` can be arbitrarily large. Zeroing a plain
+    /// file on Linux uses `fallocate(FALLOC_FL_ZERO_RANGE)` when the file system supports it;
+    /// note that this may free the underlying blocks rather than overwrite them.
+    fn fill_at(&mut self, at: u64, len: u64, byte: u8) -> Result<(), RafError> {
+        #[cfg(target_os = "linux")]
+        {
+            if byte == 0 {
+                if let Some(file) = self.as_file() {
+                    if backend::std_file::zero_range(file, at, len)? {
+                        return Ok(());
+                    }
+                }
             }
         }
+        let buf = vec![byte; cmp::min(len, COPY_BUFFER_SIZE) as usize];
+        let mut filled = 0u64;
+        while filled < len {
+            let n = cmp::min(buf.len() as u64, len - filled) as usize;
+            self.write_all_at(at + filled, &buf[..n])?;
+            filled += n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// A `RandomAccessFile` that can be opened from a path, creating the file if it does not exist.
+/// Backends that are not backed by a path, like `MemoryRandomAccessFile`, don't implement it.
+pub trait OpenRandomAccessFile : RandomAccessFile {
+    /// Opens `path` for reading and writing, creating it if it does not exist.
+    fn new<P: AsRef<Path>>(path: P) -> Result<Self, RafError> {
+        Self::open_with(path, RafOptions::new().create(true))
+    }
+    /// Opens `path` as described by `options`; usually called through `RafOptions::open`.
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<Self, RafError>;
+}
+
+/// Positioned reads and writes through a shared reference, for backends that have no cursor to
+/// move. One handle, for example an `Arc<File>`, can then be used by several threads at once.
+/// Implemented for `File` on Unix (`pread`/`pwrite`) and Windows (`seek_read`/`seek_write`, which
+/// do move the cursor, so don't mix them with `Read`/`Write` on the same handle there).
+pub trait SharedRandomAccessFile {
+    fn read_at_shared(&self, at: u64, dat: &mut [u8]) -> Result<usize, RafError>;
+    fn write_at_shared(&self, at: u64, data: &[u8]) -> Result<usize, RafError>;
+
+    /// The `&self` counterpart of `RandomAccessFile::read_exact_at`.
+    fn read_exact_at_shared(&self, at: u64, dat: &mut [u8]) -> Result<(), RafError> {
+        let mut filled = 0;
+        while filled < dat.len() {
+            match self.read_at_shared(at + filled as u64, &mut dat[filled..]) {
+                Ok(0) => return Err(RafError::ShortRead { offset: at, expected: dat.len(), read: filled }),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    /// The `&self` counterpart of `RandomAccessFile::write_all_at`.
+    fn write_all_at_shared(&self, at: u64, data: &[u8]) -> Result<(), RafError> {
+        let mut written = 0;
+        while written < data.len() {
+            match self.write_at_shared(at + written as u64, &data[written..]) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write whole buffer").into()),
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
     }
 }
 
 pub trait Serialize where Self: Sized {
     type DeserializeOutput: Sized;
-    fn serialize(&self, to: &mut Write) -> Result<(), Error>;
-    fn deserialize(from: &mut Read) -> Result<Self::DeserializeOutput, Error>;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error>;
+    fn deserialize(from: &mut dyn Read) -> Result<Self::DeserializeOutput, Error>;
+}
+
+// Reads a u64 length prefix and that many elements. A prefix larger than the data that follows
+// it, or too large to allocate, is reported as `RafError::CorruptLength` rather than
+// `UnexpectedEof` or an allocation failure.
+fn deserialize_vec<T: Serialize<DeserializeOutput = T>>(from: &mut dyn Read) -> Result<Vec<T>, Error> {
+    let size = u64::deserialize(from)?;
+    let corrupt = || Error::from(RafError::CorruptLength { len: size });
+    if size.checked_mul(mem::size_of::<T>() as u64).is_none_or(|bytes| bytes > isize::MAX as u64) {
+        return Err(corrupt());
+    }
+    // Don't trust the prefix with a large up front allocation; the vector grows as data arrives.
+    let mut ret = Vec::with_capacity(cmp::min(size, 4096) as usize);
+    for _ in 0..size {
+        match T::deserialize(from) {
+            Ok(x) => ret.push(x),
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Err(corrupt()),
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(ret)
 }
 
 macro_rules! serialize_primitive {
     ( $prim:ty, $size:expr ) => (
         impl Serialize for $prim {
             type DeserializeOutput = $prim;
-            fn deserialize(from: &mut Read) -> Result<Self, Error> {
+            fn deserialize(from: &mut dyn Read) -> Result<Self, Error> {
                 let mut buffer = vec![0u8; $size];
 
                 match from.read_exact(&mut buffer) {
@@ -103,7 +536,7 @@ macro_rules! serialize_primitive {
                     Err(e) => Err(e)
                 }
             }
-            fn serialize(&self, to: &mut Write) -> Result<(), Error> {
+            fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
                 let x = [*self];
                 let y = unsafe { slice::from_raw_parts((&x).as_ptr() as *const u8, $size) };
                 if let Err(e) = to.write_all(y) {
@@ -115,25 +548,10 @@ macro_rules! serialize_primitive {
         }
         impl Serialize for Vec<$prim> {
             type DeserializeOutput = Vec<$prim>;
-            fn deserialize(from: &mut Read) -> Result<Self, Error> {
-                let size: u64;
-                match u64::deserialize(from) {
-                    Ok(x) => {
-                        size = x;
-                    },
-                    Err(e) => return Err(e)
-                };
-                type S = $prim;
-                let mut ret = Vec::with_capacity(size as usize);
-                for _ in 0..size {
-                    match S::deserialize(from) {
-                        Ok(x) => ret.push(x),
-                        Err(e) => return Err(e)
-                    };
-                }
-                Ok(ret)
+            fn deserialize(from: &mut dyn Read) -> Result<Self, Error> {
+                deserialize_vec::<$prim>(from)
             }
-            fn serialize(&self, to: &mut Write) -> Result<(), Error> {
+            fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
                 match (self.len() as u64).serialize(to) {
                     Err(e) => return Err(e),
                     Ok(_) => ()
@@ -149,25 +567,10 @@ macro_rules! serialize_primitive {
 
         impl<'b> Serialize for &'b [$prim] {
             type DeserializeOutput = Vec<$prim>;
-            fn deserialize(from: &mut Read) -> Result<Self::DeserializeOutput, Error> {
-                let size: u64;
-                match u64::deserialize(from) {
-                    Ok(x) => {
-                        size = x;
-                    },
-                    Err(e) => return Err(e)
-                };
-                type S = $prim;
-                let mut ret = Vec::with_capacity(size as usize);
-                for _ in 0..size {
-                    match S::deserialize(from) {
-                        Ok(x) => ret.push(x),
-                        Err(e) => return Err(e)
-                    };
-                }
-                Ok(ret)
+            fn deserialize(from: &mut dyn Read) -> Result<Self::DeserializeOutput, Error> {
+                deserialize_vec::<$prim>(from)
             }
-            fn serialize(&self, to: &mut Write) -> Result<(), Error> {
+            fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
                 match (self.len() as u64).serialize(to) {
                     Err(e) => return Err(e),
                     Ok(_) => ()
@@ -185,7 +588,6 @@ macro_rules! serialize_primitive {
 
 serialize_primitive!(i8,  SIZE_OF_I8);
 serialize_primitive!(u64, SIZE_OF_U64);
-serialize_primitive!(usize, mem::size_of::<usize>());
 serialize_primitive!(u8,  SIZE_OF_U8);
 
 
@@ -198,12 +600,51 @@ serialize_primitive!(u32, SIZE_OF_U32);
 serialize_primitive!(f32, SIZE_OF_U32);
 serialize_primitive!(f64, SIZE_OF_U64);
 
+// usize is encoded as a u64 so files are portable between 32 and 64 bit targets.
+impl Serialize for usize {
+    type DeserializeOutput = usize;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
+        (*self as u64).serialize(to)
+    }
+    fn deserialize(from: &mut dyn Read) -> Result<usize, Error> {
+        let x = u64::deserialize(from)?;
+        if x > usize::MAX as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, "value does not fit in a usize on this target"));
+        }
+        Ok(x as usize)
+    }
+}
+
+impl Serialize for Vec<usize> {
+    type DeserializeOutput = Vec<usize>;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
+        (&self[..]).serialize(to)
+    }
+    fn deserialize(from: &mut dyn Read) -> Result<Vec<usize>, Error> {
+        deserialize_vec::<usize>(from)
+    }
+}
+
+impl Serialize for &[usize] {
+    type DeserializeOutput = Vec<usize>;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
+        (self.len() as u64).serialize(to)?;
+        for x in self.iter() {
+            x.serialize(to)?;
+        }
+        Ok(())
+    }
+    fn deserialize(from: &mut dyn Read) -> Result<Vec<usize>, Error> {
+        deserialize_vec::<usize>(from)
+    }
+}
+
 impl Serialize for String {
     type DeserializeOutput = String;
-    fn serialize(&self, from: &mut Write) -> Result<(), Error> {
+    fn serialize(&self, from: &mut dyn Write) -> Result<(), Error> {
         self.as_bytes().serialize(from)
     }
-    fn deserialize(to: &mut Read) -> Result<Self, Error> {
+    fn deserialize(to: &mut dyn Read) -> Result<Self, Error> {
         match Vec::<u8>::deserialize(to) {
             Ok(ret) => {
                 Ok(String::from_utf8_lossy(&ret).into_owned())
@@ -213,12 +654,12 @@ impl Serialize for String {
     }
 }
 
-impl<'a> Serialize for &'a str {
+impl Serialize for &str {
     type DeserializeOutput = String;
-    fn serialize(&self, from: &mut Write) -> Result<(), Error> {
+    fn serialize(&self, from: &mut dyn Write) -> Result<(), Error> {
         self.as_bytes().serialize(from)
     }
-    fn deserialize(to: &mut Read) -> Result<String, Error> {
+    fn deserialize(to: &mut dyn Read) -> Result<String, Error> {
         match Vec::<u8>::deserialize(to) {
             Ok(ret) => {
                 Ok(String::from_utf8_lossy(&ret).into_owned())
@@ -232,17 +673,105 @@ impl<'a> Serialize for &'a str {
 #[cfg(test)]
 mod tests {
     use Serialize;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RafError;
+    use RafSlice;
     use RandomAccessFile;
-    use cfile_rs;
-    use cfile_rs::CFile;
+    use SeekableRaf;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Cursor;
+    use std::io::ErrorKind;
     use std::io::SeekFrom;
     use std::io::Seek;
+
+    // Checks the partial transfer rules documented on `read_at` and `write_at` against a backend
+    // holding b"0123456789".
+    fn check_partial_transfers<R: RandomAccessFile>(mut raf: R) {
+        let mut buf = [b'.'; 6];
+        assert_eq!(raf.read_at(7, &mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"789...");
+        assert_eq!(raf.read_at(10, &mut buf).unwrap(), 0);
+        assert_eq!(raf.read_at(1 << 40, &mut buf).unwrap(), 0);
+        assert_eq!(&buf, b"789...");
+
+        let written = raf.write_at(8, b"abcd").unwrap();
+        assert!(written > 0 && written <= 4);
+        let mut tail = vec![0u8; written];
+        raf.read_exact_at(8, &mut tail).unwrap();
+        assert_eq!(&tail[..], &b"abcd"[..written]);
+        assert_eq!(raf.len().unwrap(), 8 + written as u64);
+    }
+
+    #[test]
+    fn partial_transfers() {
+        check_partial_transfers(MemoryRandomAccessFile::from_vec(b"0123456789".to_vec()));
+        check_partial_transfers(SeekableRaf::new(Cursor::new(b"0123456789".to_vec())));
+        check_partial_transfers(RafSlice::new(MemoryRandomAccessFile::from_vec(b"xx0123456789".to_vec()), 2, 11));
+
+        let path = env::temp_dir().join("raf_partial_transfers.bin");
+        fs::write(&path, b"0123456789").unwrap();
+        check_partial_transfers::<File>(OpenRandomAccessFile::new(&path).unwrap());
+        #[cfg(feature = "mmap")]
+        {
+            fs::write(&path, b"0123456789").unwrap();
+            check_partial_transfers::<::MmapRandomAccessFile>(OpenRandomAccessFile::new(&path).unwrap());
+        }
+        let _ = fs::remove_file(&path);
+    }
+
     #[test]
     fn it_works() {
-        let mut raf: CFile = RandomAccessFile::new("test.txt").unwrap();
-        65u64.serialize(&mut raf);
-        raf.seek(SeekFrom::Start(0));
-        let mut t = u64::deserialize(&mut raf).unwrap();
+        let mut raf: File = OpenRandomAccessFile::new("test.txt").unwrap();
+        65u64.serialize(&mut raf).unwrap();
+        raf.seek(SeekFrom::Start(0)).unwrap();
+        let t = u64::deserialize(&mut raf).unwrap();
         assert!(t == 65)
     }
+
+    #[test]
+    fn exact_and_all_at() {
+        let mut raf = MemoryRandomAccessFile::new();
+        raf.write_all_at(2, b"abcd").unwrap();
+        let mut buf = [0u8; 3];
+        raf.read_exact_at(3, &mut buf).unwrap();
+        assert_eq!(&buf, b"bcd");
+        assert_eq!(raf.read_exact_at(4, &mut buf).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn usize_is_portable() {
+        let mut encoded = Vec::new();
+        7usize.serialize(&mut encoded).unwrap();
+        vec![1usize, 2].serialize(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), 8 + 8 + 2 * 8);
+        let mut from = &encoded[..];
+        assert_eq!(usize::deserialize(&mut from).unwrap(), 7);
+        assert_eq!(Vec::<usize>::deserialize(&mut from).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn error_variants() {
+        let mut raf = MemoryRandomAccessFile::from_vec(b"abcd".to_vec());
+        match raf.read_exact_at(2, &mut [0u8; 4]) {
+            Err(RafError::ShortRead { offset: 2, expected: 4, read: 2 }) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+        match raf.at(4) {
+            Err(RafError::PastEof { offset: 4 }) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+
+        let mut encoded = Vec::new();
+        vec![1u32, 2, 3].serialize(&mut encoded).unwrap();
+        encoded[0] = 200;
+        let e = Vec::<u32>::deserialize(&mut &encoded[..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        match RafError::from(e) {
+            RafError::CorruptLength { len: 200 } => (),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
 }
@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::fs::TryLockError;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// A `RandomAccessFile` holding an advisory lock on its file, which is released when the handle
+/// is dropped. Unlike `ExclusiveRaf`, which only guards against other handles in the same process,
+/// the lock is taken through the operating system (`flock` on Unix, `LockFileEx` on Windows), so it
+/// also keeps out other processes, provided they lock the file too; being advisory, it doesn't
+/// stop anyone who doesn't.
+///
+/// Locking requires a backend that exposes its file through `as_file`, such as `File` or an
+/// `ExclusiveRaf<File>`; any other backend is rejected with `ErrorKind::Unsupported`. A shared lock
+/// doesn't prevent writes through this handle, it only guarantees that no other handle holds an
+/// exclusive one.
+#[derive(Debug)]
+pub struct LockedRaf<R: RandomAccessFile> {
+    inner: R,
+    exclusive: bool,
+}
+
+fn file_of<R: RandomAccessFile>(inner: &R) -> Result<&File, RafError> {
+    inner.as_file()
+        .ok_or_else(|| Error::new(ErrorKind::Unsupported, "this backend cannot be locked").into())
+}
+
+fn try_lock_error(e: TryLockError) -> RafError {
+    match e {
+        TryLockError::WouldBlock => Error::new(ErrorKind::WouldBlock, "the file is locked by another handle").into(),
+        TryLockError::Error(e) => e.into()
+    }
+}
+
+/// Writes `new` at `at` if the bytes there are `expected`, without taking any lock. A range that
+/// extends past the end of the file doesn't match.
+pub(crate) fn compare_and_write<R: RandomAccessFile>(raf: &mut R, at: u64, expected: &[u8], new: &[u8])
+    -> Result<bool, RafError> {
+    if expected.len() != new.len() {
+        return Err(Error::new(ErrorKind::InvalidInput, "expected and new values differ in length").into());
+    }
+    let mut current = vec![0u8; expected.len()];
+    match raf.read_exact_at(at, &mut current) {
+        Ok(()) => (),
+        Err(RafError::ShortRead { .. }) => return Ok(false),
+        Err(e) => return Err(e)
+    }
+    if current != expected {
+        return Ok(false);
+    }
+    raf.write_all_at(at, new)?;
+    Ok(true)
+}
+
+impl<R: RandomAccessFile> LockedRaf<R> {
+    /// Takes an exclusive lock on the file, waiting until no other handle holds a lock on it.
+    pub fn lock_exclusive(inner: R) -> Result<LockedRaf<R>, RafError> {
+        file_of(&inner)?.lock()?;
+        Ok(LockedRaf { inner, exclusive: true })
+    }
+
+    /// Takes a shared lock on the file, waiting until no other handle holds an exclusive one.
+    pub fn lock_shared(inner: R) -> Result<LockedRaf<R>, RafError> {
+        file_of(&inner)?.lock_shared()?;
+        Ok(LockedRaf { inner, exclusive: false })
+    }
+
+    /// Like `lock_exclusive`, but fails with `ErrorKind::WouldBlock` instead of waiting.
+    pub fn try_lock_exclusive(inner: R) -> Result<LockedRaf<R>, RafError> {
+        file_of(&inner)?.try_lock().map_err(try_lock_error)?;
+        Ok(LockedRaf { inner, exclusive: true })
+    }
+
+    /// Like `lock_shared`, but fails with `ErrorKind::WouldBlock` instead of waiting.
+    pub fn try_lock_shared(inner: R) -> Result<LockedRaf<R>, RafError> {
+        file_of(&inner)?.try_lock_shared().map_err(try_lock_error)?;
+        Ok(LockedRaf { inner, exclusive: false })
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: RandomAccessFile> Drop for LockedRaf<R> {
+    fn drop(&mut self) {
+        if let Some(file) = self.inner.as_file() {
+            let _ = file.unlock();
+        }
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for LockedRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.inner.read_at(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.inner.write_at(at, data)
+    }
+
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        self.inner.read_at_vectored(at, bufs)
+    }
+
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        self.inner.write_at_vectored(at, bufs)
+    }
+
+    /// With an exclusive lock no other handle can get in between the read and the write, so no
+    /// further locking is needed. With a shared lock this fails with `ErrorKind::WouldBlock`, since
+    /// the lock would have to be upgraded, which isn't atomic.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        if !self.exclusive {
+            return Err(Error::new(ErrorKind::WouldBlock, "compare_and_write_at needs an exclusive lock").into());
+        }
+        compare_and_write(&mut self.inner, at, expected, new)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.inner.append(data)
+    }
+
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        self.inner.append_vectored(bufs)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lock::LockedRaf;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use SeekableRaf;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Cursor;
+    use std::io::ErrorKind;
+    use std::thread;
+
+    #[test]
+    fn locks_exclude_other_handles() {
+        let path = env::temp_dir().join("raf_lock.bin");
+        let _ = fs::remove_file(&path);
+        let open = || -> File { OpenRandomAccessFile::new(&path).unwrap() };
+
+        let mut writer = LockedRaf::try_lock_exclusive(open()).unwrap();
+        writer.append(b"locked").unwrap();
+        let err = LockedRaf::try_lock_shared(open()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        drop(writer);
+
+        let mut reader = LockedRaf::try_lock_shared(open()).unwrap();
+        let other = LockedRaf::try_lock_shared(open()).unwrap();
+        assert!(!other.is_exclusive());
+        assert_eq!(LockedRaf::try_lock_exclusive(open()).unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(reader.at(0).unwrap(), b'l');
+
+        let err = LockedRaf::lock_exclusive(MemoryRandomAccessFile::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn compare_and_write_is_atomic_across_handles() {
+        let path = env::temp_dir().join("raf_compare_and_write.bin");
+        let _ = fs::remove_file(&path);
+        let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+        raf.write_all_at(0, &0u64.to_le_bytes()).unwrap();
+
+        let workers: Vec<_> = (0..4).map(|_| {
+            let mut raf: File = OpenRandomAccessFile::new(&path).unwrap();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    loop {
+                        let mut current = [0u8; 8];
+                        raf.read_exact_at(0, &mut current).unwrap();
+                        let next = (u64::from_le_bytes(current) + 1).to_le_bytes();
+                        if raf.compare_and_write_at(0, &current, &next).unwrap() {
+                            break;
+                        }
+                    }
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let mut counter = [0u8; 8];
+        raf.read_exact_at(0, &mut counter).unwrap();
+        assert_eq!(u64::from_le_bytes(counter), 200);
+        assert!(!raf.compare_and_write_at(4, &[0; 8], &[1; 8]).unwrap());
+
+        let mut shared = LockedRaf::lock_shared(raf).unwrap();
+        assert_eq!(shared.compare_and_write_at(0, &counter, &counter).unwrap_err().kind(), ErrorKind::WouldBlock);
+        drop(shared);
+        let reopened: File = OpenRandomAccessFile::new(&path).unwrap();
+        let mut exclusive = LockedRaf::lock_exclusive(reopened).unwrap();
+        assert!(exclusive.compare_and_write_at(0, &counter, &[9; 8]).unwrap());
+        let _ = fs::remove_file(&path);
+
+        // Without a file to lock, another handle could write in between.
+        let mut seekable = SeekableRaf::new(Cursor::new(vec![0u8; 8]));
+        let err = seekable.compare_and_write_at(0, &[0; 8], &[1; 8]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::fs::Permissions;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use std::time::SystemTime;
+
+/// What kind of storage a `RandomAccessFile` is backed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageKind {
+    /// A regular file on a file system.
+    File,
+    /// A block device, such as a disk or partition opened directly.
+    BlockDevice,
+    /// A buffer in memory, which is lost when the handle is dropped.
+    Memory,
+    /// Anything else, including backends that can't tell.
+    Other,
+}
+
+/// Backend independent metadata of a `RandomAccessFile`, returned by
+/// `RandomAccessFile::metadata`. The fields a backend can't provide are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RafMetadata {
+    /// The length of the file in bytes, as `RandomAccessFile::len` reports it.
+    pub len: u64,
+    /// The time the file was last modified.
+    pub modified: Option<SystemTime>,
+    /// The permissions of the file.
+    pub permissions: Option<Permissions>,
+    pub kind: StorageKind,
+}
+
+fn kind_of(file_type: fs::FileType) -> StorageKind {
+    if file_type.is_file() {
+        return StorageKind::File;
+    }
+    #[cfg(unix)]
+    {
+        if file_type.is_block_device() {
+            return StorageKind::BlockDevice;
+        }
+    }
+    StorageKind::Other
+}
+
+impl From<fs::Metadata> for RafMetadata {
+    fn from(metadata: fs::Metadata) -> RafMetadata {
+        RafMetadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            permissions: Some(metadata.permissions()),
+            kind: kind_of(metadata.file_type()),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs::File;
+use std::time::Duration;
+use std::time::Instant;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// The `RandomAccessFile` operation an `IoEvent` describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoOp {
+    Read,
+    Write,
+    Append,
+    CompareAndWrite,
+    Len,
+    SetLen,
+    Flush,
+    SyncData,
+    SyncAll,
+    Metadata,
+    Allocate,
+    Advise,
+    PunchHole,
+}
+
+/// One finished operation on an `ObservedRaf`.
+#[derive(Debug)]
+pub struct IoEvent<'a> {
+    pub op: IoOp,
+    /// The offset the operation was at, or `None` for operations without one. For an append it is
+    /// where the data landed, so `None` if it failed.
+    pub at: Option<u64>,
+    /// The number of bytes asked for: the buffer length of a transfer, or the length argument of
+    /// `set_len`, `allocate`, `advise` and `punch_hole`. 0 for everything else.
+    pub len: u64,
+    pub elapsed: Duration,
+    /// The number of bytes transferred, which is 0 for anything but a transfer, or the error. A
+    /// compare-and-write that found other bytes than expected transferred 0.
+    pub result: Result<u64, &'a RafError>,
+}
+
+/// Called by an `ObservedRaf` after every operation, to log it, time it or record it as a
+/// `tracing` event. Implemented for closures taking an `&IoEvent`.
+pub trait IoObserver {
+    fn observe(&mut self, event: &IoEvent<'_>);
+}
+
+impl<F: FnMut(&IoEvent<'_>)> IoObserver for F {
+    fn observe(&mut self, event: &IoEvent<'_>) {
+        self(event)
+    }
+}
+
+/// A `RandomAccessFile` that reports every operation, with its offset, length, duration and
+/// result, to an `IoObserver`. Provided methods like `read_exact_at` are reported as the reads
+/// and writes they are made of.
+#[derive(Debug)]
+pub struct ObservedRaf<R: RandomAccessFile, O: IoObserver> {
+    inner: R,
+    observer: O,
+}
+
+impl<R: RandomAccessFile, O: IoObserver> ObservedRaf<R, O> {
+    pub fn new(inner: R, observer: O) -> ObservedRaf<R, O> {
+        ObservedRaf { inner, observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Runs `f` on the inner file, reporting it as `op`; `transferred` picks the byte count out of
+    // a successful result.
+    fn run<T, F, B>(&mut self, op: IoOp, at: Option<u64>, len: u64, f: F, transferred: B) -> Result<T, RafError>
+        where F: FnOnce(&mut R) -> Result<T, RafError>, B: FnOnce(&T) -> u64 {
+        let start = Instant::now();
+        let result = f(&mut self.inner);
+        let elapsed = start.elapsed();
+        self.observer.observe(&IoEvent {
+            op,
+            at,
+            len,
+            elapsed,
+            result: result.as_ref().map(transferred),
+        });
+        result
+    }
+}
+
+impl<R: RandomAccessFile, O: IoObserver> RandomAccessFile for ObservedRaf<R, O> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let len = dat.len() as u64;
+        self.run(IoOp::Read, Some(at), len, |raf| raf.read_at(at, dat), |&n| n as u64)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let len = data.len() as u64;
+        self.run(IoOp::Write, Some(at), len, |raf| raf.write_at(at, data), |&n| n as u64)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let len = data.len() as u64;
+        let start = Instant::now();
+        let result = self.inner.append(data);
+        let elapsed = start.elapsed();
+        self.observer.observe(&IoEvent {
+            op: IoOp::Append,
+            at: result.as_ref().ok().cloned(),
+            len,
+            elapsed,
+            result: result.as_ref().map(|_| len),
+        });
+        result
+    }
+
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        let len = new.len() as u64;
+        self.run(IoOp::CompareAndWrite, Some(at), len, |raf| raf.compare_and_write_at(at, expected, new),
+            |&written| if written { len } else { 0 })
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.run(IoOp::Len, None, 0, |raf| raf.len(), |_| 0)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.run(IoOp::SetLen, None, len, |raf| raf.set_len(len), |_| 0)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.run(IoOp::Flush, None, 0, |raf| raf.flush(), |_| 0)
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.run(IoOp::SyncData, None, 0, |raf| raf.sync_data(), |_| 0)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.run(IoOp::SyncAll, None, 0, |raf| raf.sync_all(), |_| 0)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.run(IoOp::Metadata, None, 0, |raf| raf.metadata(), |_| 0)
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.run(IoOp::Allocate, None, len, |raf| raf.allocate(len), |_| 0)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.run(IoOp::Advise, Some(at), len, |raf| raf.advise(at, len, advice), |_| 0)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.run(IoOp::PunchHole, Some(at), len, |raf| raf.punch_hole(at, len), |_| 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use observe::{IoEvent, IoOp, ObservedRaf};
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+
+    #[test]
+    fn reports_every_operation() {
+        let mut events = Vec::new();
+        {
+            let record = |event: &IoEvent<'_>| {
+                events.push((event.op, event.at, event.len, event.result.as_ref().ok().cloned()));
+            };
+            let mut raf = ObservedRaf::new(MemoryRandomAccessFile::new(), record);
+            raf.append(b"observed").unwrap();
+            let mut buf = [0u8; 16];
+            assert_eq!(raf.read_at(4, &mut buf).unwrap(), 4);
+            raf.set_len(2).unwrap();
+            assert!(raf.read_exact_at(0, &mut buf).is_err());
+            assert!(!raf.compare_and_write_at(0, b"no", b"ok").unwrap());
+        }
+        assert_eq!(events, vec![
+            (IoOp::Append, Some(0), 8, Some(8)),
+            (IoOp::Read, Some(4), 16, Some(4)),
+            (IoOp::SetLen, None, 2, Some(0)),
+            (IoOp::Read, Some(0), 16, Some(2)),
+            (IoOp::Read, Some(2), 14, Some(0)),
+            (IoOp::CompareAndWrite, Some(0), 2, Some(0)),
+        ]);
+    }
+}
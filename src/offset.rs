@@ -0,0 +1,111 @@
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::Error;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+use RandomAccessFile;
+use Serialize;
+
+/// A typed file pointer: "a `T` is stored at this offset". It serializes as a plain `u64`, so it
+/// can be embedded in other on-disk values to build pointer graphs that are type checked.
+pub struct Offset<T> {
+    at: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Offset<T> {
+    pub fn new(at: u64) -> Offset<T> {
+        Offset { at, _marker: PhantomData }
+    }
+
+    /// The raw byte offset.
+    pub fn get(&self) -> u64 {
+        self.at
+    }
+
+    /// Reinterprets the offset as pointing to a different type.
+    pub fn cast<U>(self) -> Offset<U> {
+        Offset::new(self.at)
+    }
+}
+
+impl<T: Serialize> Offset<T> {
+    /// Deserializes the `T` stored at this offset.
+    pub fn read<R: RandomAccessFile>(&self, raf: &mut R) -> Result<T::DeserializeOutput, Error> {
+        T::deserialize(&mut raf.reader_at(self.at, u64::MAX))
+    }
+
+    /// Serializes `value` at this offset. The caller is responsible for there being enough room.
+    pub fn write<R: RandomAccessFile>(&self, raf: &mut R, value: &T) -> Result<(), Error> {
+        let mut encoded = Vec::new();
+        value.serialize(&mut encoded)?;
+        raf.write_all_at(self.at, &encoded).map_err(Error::from)
+    }
+}
+
+impl<T> Clone for Offset<T> {
+    fn clone(&self) -> Offset<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Offset<T> {}
+
+impl<T> PartialEq for Offset<T> {
+    fn eq(&self, other: &Offset<T>) -> bool {
+        self.at == other.at
+    }
+}
+
+impl<T> Eq for Offset<T> {}
+
+impl<T> Hash for Offset<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.at.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Offset<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Offset({})", self.at)
+    }
+}
+
+impl<T> Serialize for Offset<T> {
+    type DeserializeOutput = Offset<T>;
+    fn serialize(&self, to: &mut dyn Write) -> Result<(), Error> {
+        self.at.serialize(to)
+    }
+    fn deserialize(from: &mut dyn Read) -> Result<Offset<T>, Error> {
+        u64::deserialize(from).map(Offset::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use offset::Offset;
+    use OpenRandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+
+    #[test]
+    fn follow_pointer() {
+        let path = env::temp_dir().join("raf_offset.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+
+        let name: Offset<String> = Offset::new(64);
+        name.write(&mut raf, &String::from("hello")).unwrap();
+        let root: Offset<Offset<String>> = Offset::new(0);
+        root.write(&mut raf, &name).unwrap();
+
+        let found = root.read(&mut raf).unwrap();
+        assert_eq!(found, name);
+        assert_eq!(found.read(&mut raf).unwrap(), "hello");
+        let _ = fs::remove_file(path);
+    }
+}
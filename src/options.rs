@@ -0,0 +1,176 @@
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use libc;
+use OpenRandomAccessFile;
+use RafError;
+
+/// Options for opening a `RandomAccessFile` from a path, in the spirit of `std::fs::OpenOptions`.
+/// `open` works with any backend that implements `OpenRandomAccessFile`.
+///
+/// With no options set the file must already exist and is opened for reading and writing.
+/// `OpenRandomAccessFile::new` is the same as `RafOptions::new().create(true).open(path)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RafOptions {
+    create: bool,
+    create_new: bool,
+    read_only: bool,
+    truncate: bool,
+    mode: Option<u32>,
+    direct: bool,
+    share_mode: Option<u32>,
+}
+
+impl RafOptions {
+    pub fn new() -> RafOptions {
+        RafOptions::default()
+    }
+
+    /// Create the file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut RafOptions {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing with `AlreadyExists` if it is already there.
+    pub fn create_new(&mut self, create_new: bool) -> &mut RafOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Open the file for reading only. Cannot be combined with `create`, `create_new` or
+    /// `truncate`, and not every backend supports it.
+    ///
+    /// The backend is still of a type with write methods, which fail at run time. For a handle
+    /// the type system keeps from writing, use `ReadOnlyRaf::open`, or `ReadOnlyRaf::wrap` the
+    /// opened backend.
+    pub fn read_only(&mut self, read_only: bool) -> &mut RafOptions {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Truncate the file to zero length when opening it.
+    pub fn truncate(&mut self, truncate: bool) -> &mut RafOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    /// The permission bits a newly created file gets (before the umask is applied). Only has an
+    /// effect on Unix.
+    pub fn mode(&mut self, mode: u32) -> &mut RafOptions {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Open the file for direct I/O (`O_DIRECT`), bypassing the page cache. Transfers on such a
+    /// file must be aligned to the device's block size, so open it as a `DirectRandomAccessFile`,
+    /// which takes care of that. Only has an effect on Linux.
+    pub fn direct(&mut self, direct: bool) -> &mut RafOptions {
+        self.direct = direct;
+        self
+    }
+
+    /// Which access other handles may open the file with while this one is open, as a
+    /// combination of the Windows `FILE_SHARE_READ` (1), `FILE_SHARE_WRITE` (2) and
+    /// `FILE_SHARE_DELETE` (4) flags; 0 opens it exclusively. The default, like `std`'s, shares
+    /// all three. Only has an effect on Windows.
+    pub fn share_mode(&mut self, share_mode: u32) -> &mut RafOptions {
+        self.share_mode = Some(share_mode);
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Opens `path` as a backend of type `R`.
+    pub fn open<R: OpenRandomAccessFile, P: AsRef<Path>>(&self, path: P) -> Result<R, RafError> {
+        R::open_with(path, self)
+    }
+
+    /// The equivalent `std::fs::OpenOptions`, for backends that open a `File` themselves.
+    pub fn to_open_options(&self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        options.read(true)
+            .write(!self.read_only)
+            .create(self.create)
+            .create_new(self.create_new)
+            .truncate(self.truncate);
+        #[cfg(unix)]
+        {
+            if let Some(mode) = self.mode {
+                options.mode(mode);
+            }
+        }
+        #[cfg(windows)]
+        {
+            if let Some(share_mode) = self.share_mode {
+                options.share_mode(share_mode);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if self.direct {
+                options.custom_flags(libc::O_DIRECT);
+            }
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use options::RafOptions;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn builder_semantics() {
+        let path = &env::temp_dir().join("raf_options.bin");
+        let _ = fs::remove_file(path);
+
+        assert_eq!(RafOptions::new().open::<File, _>(path).unwrap_err().kind(), ErrorKind::NotFound);
+        let mut raf: File = RafOptions::new().create_new(true).mode(0o600).open(path).unwrap();
+        raf.write_all_at(0, b"hello").unwrap();
+        let err = RafOptions::new().create_new(true).open::<File, _>(path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let mut ro: File = RafOptions::new().read_only(true).open(path).unwrap();
+        assert_eq!(ro.at(1).unwrap(), b'e');
+        assert!(ro.write_at(0, b"x").is_err());
+
+        let mut raf: File = RafOptions::new().truncate(true).open(path).unwrap();
+        assert!(raf.is_empty().unwrap());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn share_mode_conflicts() {
+        let path = &env::temp_dir().join("raf_options_share.bin");
+        let _ = fs::remove_file(path);
+
+        let exclusive: File = RafOptions::new().create(true).share_mode(0).open(path).unwrap();
+        assert!(RafOptions::new().read_only(true).open::<File, _>(path).is_err());
+        drop(exclusive);
+
+        // FILE_SHARE_READ lets readers in, but not writers.
+        let reader_only: File = RafOptions::new().share_mode(1).open(path).unwrap();
+        RafOptions::new().read_only(true).open::<File, _>(path).unwrap();
+        assert!(RafOptions::new().open::<File, _>(path).is_err());
+        drop(reader_only);
+        let _ = fs::remove_file(path);
+    }
+}
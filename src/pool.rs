@@ -0,0 +1,196 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use OpenRandomAccessFile;
+use RafError;
+use RafOptions;
+
+#[derive(Debug)]
+struct Handles<R> {
+    idle: Vec<R>,
+    // Idle and checked out handles, plus any being opened.
+    open: usize,
+}
+
+/// A pool of up to `max` handles to the same path, for threads that each want a handle of their
+/// own, for example so that backends with a cursor don't make concurrent readers wait on each
+/// other. Handles are opened, with the pool's `RafOptions`, the first time they are needed, and
+/// kept open for reuse once they are checked back in.
+///
+/// `checkout` hands out a `PooledRaf`, which checks the handle back in when it is dropped.
+#[derive(Debug)]
+pub struct RafPool<R: OpenRandomAccessFile> {
+    path: PathBuf,
+    options: RafOptions,
+    max: usize,
+    handles: Mutex<Handles<R>>,
+    checked_in: Condvar,
+}
+
+impl<R: OpenRandomAccessFile> RafPool<R> {
+    /// A pool of at most `max` handles to `path`, none of which are opened yet. Panics if `max`
+    /// is 0.
+    pub fn new<P: AsRef<Path>>(path: P, options: RafOptions, max: usize) -> RafPool<R> {
+        assert!(max != 0, "a pool needs room for at least one handle");
+        RafPool {
+            path: path.as_ref().to_path_buf(),
+            options,
+            max,
+            handles: Mutex::new(Handles { idle: Vec::new(), open: 0 }),
+            checked_in: Condvar::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// The number of handles currently open, whether idle or checked out.
+    pub fn open_count(&self) -> usize {
+        self.lock().open
+    }
+
+    /// Checks out an idle handle, opening a new one if there is none and the pool isn't full, or
+    /// else waiting for one to be checked in.
+    pub fn checkout(&self) -> Result<PooledRaf<'_, R>, RafError> {
+        let mut handles = self.lock();
+        loop {
+            if let Some(raf) = handles.idle.pop() {
+                return Ok(PooledRaf { pool: self, raf: Some(raf) });
+            }
+            if handles.open < self.max {
+                return self.open(handles);
+            }
+            handles = self.checked_in.wait(handles).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Like `checkout`, but fails with `ErrorKind::WouldBlock` instead of waiting.
+    pub fn try_checkout(&self) -> Result<PooledRaf<'_, R>, RafError> {
+        let mut handles = self.lock();
+        if let Some(raf) = handles.idle.pop() {
+            return Ok(PooledRaf { pool: self, raf: Some(raf) });
+        }
+        if handles.open < self.max {
+            return self.open(handles);
+        }
+        Err(Error::new(ErrorKind::WouldBlock, "every handle in the pool is checked out").into())
+    }
+
+    // Opens a new handle without holding the lock, having reserved room for it.
+    fn open(&self, mut handles: MutexGuard<'_, Handles<R>>) -> Result<PooledRaf<'_, R>, RafError> {
+        handles.open += 1;
+        drop(handles);
+        match R::open_with(&self.path, &self.options) {
+            Ok(raf) => Ok(PooledRaf { pool: self, raf: Some(raf) }),
+            Err(e) => {
+                self.close_one();
+                Err(e)
+            }
+        }
+    }
+
+    fn close_one(&self) {
+        self.lock().open -= 1;
+        self.checked_in.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Handles<R>> {
+        self.handles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A handle checked out of a `RafPool`, which dereferences to the backend. Dropping it checks the
+/// handle back in.
+#[derive(Debug)]
+pub struct PooledRaf<'a, R: OpenRandomAccessFile + 'a> {
+    pool: &'a RafPool<R>,
+    // Only None once dropped or discarded.
+    raf: Option<R>,
+}
+
+impl<'a, R: OpenRandomAccessFile> PooledRaf<'a, R> {
+    /// Closes the handle instead of checking it back in, for a handle that is no longer usable,
+    /// say after an error. The pool opens a fresh one when it next needs to.
+    pub fn discard(mut self) {
+        self.raf = None;
+        self.pool.close_one();
+    }
+}
+
+impl<'a, R: OpenRandomAccessFile> Deref for PooledRaf<'a, R> {
+    type Target = R;
+    fn deref(&self) -> &R {
+        self.raf.as_ref().expect("the handle is only taken on drop")
+    }
+}
+
+impl<'a, R: OpenRandomAccessFile> DerefMut for PooledRaf<'a, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.raf.as_mut().expect("the handle is only taken on drop")
+    }
+}
+
+impl<'a, R: OpenRandomAccessFile> Drop for PooledRaf<'a, R> {
+    fn drop(&mut self) {
+        if let Some(raf) = self.raf.take() {
+            self.pool.lock().idle.push(raf);
+            self.pool.checked_in.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pool::RafPool;
+    use RafOptions;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+    use std::thread;
+
+    #[test]
+    fn checkout_reuses_and_waits_for_handles() {
+        let path = env::temp_dir().join("raf_pool.bin");
+        fs::write(&path, b"pooled handles").unwrap();
+        let pool: RafPool<File> = RafPool::new(&path, RafOptions::new(), 2);
+
+        let mut first = pool.checkout().unwrap();
+        let second = pool.checkout().unwrap();
+        assert_eq!(pool.try_checkout().unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(first.at(0).unwrap(), b'p');
+        drop(first);
+        second.discard();
+        assert_eq!(pool.open_count(), 1);
+
+        thread::scope(|scope| {
+            for i in 0..4u64 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        let mut raf = pool.checkout().unwrap();
+                        assert_eq!(raf.at(7 + i).unwrap(), b"handles"[i as usize]);
+                    }
+                });
+            }
+        });
+        assert!(pool.open_count() <= 2);
+
+        let missing: RafPool<File> = RafPool::new(env::temp_dir().join("raf_pool_missing.bin"), RafOptions::new(), 1);
+        assert_eq!(missing.checkout().unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(missing.open_count(), 0);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,171 @@
+use std::cmp;
+use std::io::Error;
+use std::io::ErrorKind;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// A view of the `len` bytes of a file starting at `offset`, which is itself a `RandomAccessFile`
+/// with offsets relative to the start of the region. Everything outside the region is out of
+/// reach: reads stop at its end, and writes past it fail with `ErrorKind::InvalidInput`, or are
+/// cut short if they start inside it.
+///
+/// The length of the slice is the part of the region that exists in the file, so it grows as
+/// data is written into the region, up to the region's size. To let several subsystems each work
+/// on their own region at the same time, give each slice its own handle with `try_clone`.
+#[derive(Debug)]
+pub struct RafSlice<R: RandomAccessFile> {
+    inner: R,
+    offset: u64,
+    len: u64,
+}
+
+impl<R: RandomAccessFile> RafSlice<R> {
+    /// Panics if the region extends past `u64::MAX`.
+    pub fn new(inner: R, offset: u64, len: u64) -> RafSlice<R> {
+        assert!(offset.checked_add(len).is_some(), "slice extends past the end of the address space");
+        RafSlice { inner, offset, len }
+    }
+
+    /// The offset of the region in the underlying file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The size of the region, which `len` never exceeds.
+    pub fn capacity(&self) -> u64 {
+        self.len
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // The number of bytes of the region available from `at`.
+    fn room(&self, at: u64) -> u64 {
+        self.len.saturating_sub(at)
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for RafSlice<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let n = cmp::min(self.room(at), dat.len() as u64) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.inner.read_at(self.offset + at, &mut dat[..n])
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let n = cmp::min(self.room(at), data.len() as u64) as usize;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "write past the end of the slice").into());
+        }
+        self.inner.write_at(self.offset + at, &data[..n])
+    }
+
+    /// Writes at the end of the slice, failing if the data doesn't fit in the rest of the region.
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.len()?;
+        if data.len() as u64 > self.room(at) {
+            return Err(Error::new(ErrorKind::InvalidInput, "append past the end of the slice").into());
+        }
+        self.write_all_at(at, data)?;
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        let inner_len = self.inner.len()?;
+        Ok(cmp::min(inner_len.saturating_sub(self.offset), self.len))
+    }
+
+    /// Only extends the slice, within its region; shrinking it would truncate the underlying file,
+    /// and with it whatever follows the region.
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        if len > self.len {
+            return Err(Error::new(ErrorKind::InvalidInput, "set_len past the end of the slice").into());
+        }
+        let current = self.len()?;
+        if len < current {
+            return Err(Error::new(ErrorKind::Unsupported, "a slice cannot be truncated").into());
+        }
+        if len > current {
+            self.inner.set_len(self.offset + len)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        let len = self.len()?;
+        Ok(RafMetadata { len, ..self.inner.metadata()? })
+    }
+
+    fn try_clone(&self) -> Result<RafSlice<R>, RafError> {
+        Ok(RafSlice::new(self.inner.try_clone()?, self.offset, self.len))
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        let room = self.room(at);
+        if room == 0 {
+            return Ok(());
+        }
+        let len = if len == 0 { room } else { cmp::min(len, room) };
+        self.inner.advise(self.offset + at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        let len = cmp::min(len, self.room(at));
+        if len == 0 {
+            return Ok(());
+        }
+        self.inner.punch_hole(self.offset + at, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use raf_slice::RafSlice;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn translates_and_bounds_offsets() {
+        let raf = MemoryRandomAccessFile::from_vec(b"0123456789".to_vec());
+        let mut slice = RafSlice::new(raf, 4, 8);
+        assert_eq!(slice.len().unwrap(), 6);
+        assert_eq!(slice.at(0).unwrap(), b'4');
+        let mut buf = [0u8; 16];
+        assert_eq!(slice.read_at(2, &mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"6789");
+
+        slice.append(b"ab").unwrap();
+        assert_eq!(slice.len().unwrap(), 8);
+        assert_eq!(slice.write_at(6, b"xyz").unwrap(), 2);
+        assert_eq!(slice.write_at(8, b"z").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(slice.append(b"z").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(slice.set_len(2).unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(slice.read_at(8, &mut buf).unwrap(), 0);
+        assert_eq!(slice.into_inner().as_slice(), b"0123456789xy");
+    }
+}
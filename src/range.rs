@@ -0,0 +1,141 @@
+use std::cmp;
+use std::io::Error;
+use std::io::Read;
+use std::io::Write;
+use RafError;
+use RandomAccessFile;
+
+/// A `Read` view of `len` bytes of a file starting at an offset, created by
+/// `RandomAccessFile::reader_at`. It keeps its own position and reads with `read_at`, so it
+/// doesn't disturb any cursor of the file, and reports the end of the stream at the end of the
+/// range or of the file, whichever comes first.
+pub struct RangeReader<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    pos: u64,
+    end: u64,
+}
+
+impl<'a, R: RandomAccessFile + 'a> RangeReader<'a, R> {
+    pub(crate) fn new(raf: &'a mut R, at: u64, len: u64) -> RangeReader<'a, R> {
+        RangeReader { raf, pos: at, end: at.saturating_add(len) }
+    }
+
+    /// The offset in the file the next read starts at.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// The number of bytes left in the range.
+    pub fn remaining(&self) -> u64 {
+        self.end - self.pos
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Read for RangeReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let want = cmp::min(self.remaining(), buf.len() as u64) as usize;
+        if want == 0 {
+            return Ok(0);
+        }
+        let read = self.raf.read_at(self.pos, &mut buf[..want])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+/// A `Write` positioned at an offset of a file, created by `RandomAccessFile::writer_at`, e.g. to
+/// serialize a value into a fixed-size record slot. Like `RangeReader` it writes with `write_at`
+/// and keeps its own position.
+///
+/// With a limit, a write that would go past the end of the slot fails with
+/// `RafError::SlotOverflow`, without writing any of its data; whatever earlier writes put in the
+/// slot stays there.
+pub struct RangeWriter<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    start: u64,
+    pos: u64,
+    limit: Option<u64>,
+}
+
+impl<'a, R: RandomAccessFile + 'a> RangeWriter<'a, R> {
+    pub(crate) fn new(raf: &'a mut R, at: u64, limit: Option<u64>) -> RangeWriter<'a, R> {
+        RangeWriter { raf, start: at, pos: at, limit }
+    }
+
+    /// The offset in the file the next write starts at.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// The number of bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.pos - self.start
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Write for RangeWriter<'a, R> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if let Some(limit) = self.limit {
+            if self.written().saturating_add(buf.len() as u64) > limit {
+                return Err(RafError::SlotOverflow { offset: self.start, limit }.into());
+            }
+        }
+        let written = self.raf.write_at(self.pos, buf)?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.raf.flush().map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use MemoryRandomAccessFile;
+    use RafError;
+    use RandomAccessFile;
+    use Serialize;
+    use std::io::ErrorKind;
+    use std::io::Read;
+
+    #[test]
+    fn reads_a_bounded_range() {
+        let mut raf = MemoryRandomAccessFile::new();
+        raf.write_all_at(0, b"header").unwrap();
+        let mut encoded = Vec::new();
+        vec![1u32, 2, 3].serialize(&mut encoded).unwrap();
+        raf.write_all_at(6, &encoded).unwrap();
+
+        let len = encoded.len() as u64;
+        assert_eq!(Vec::<u32>::deserialize(&mut raf.reader_at(6, len)).unwrap(), vec![1, 2, 3]);
+        let err = Vec::<u32>::deserialize(&mut raf.reader_at(6, len - 1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut reader = raf.reader_at(2, 100);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..4], b"ader");
+        assert_eq!(reader.position(), 6 + len);
+    }
+
+    #[test]
+    fn writes_into_a_bounded_slot() {
+        let mut raf = MemoryRandomAccessFile::from_vec(vec![0xff; 16]);
+        let value = vec![7u16, 8, 9];
+        value.serialize(&mut raf.writer_at(2, Some(14))).unwrap();
+        assert_eq!(Vec::<u16>::deserialize(&mut raf.reader_at(2, 14)).unwrap(), value);
+
+        let err = value.serialize(&mut raf.writer_at(8, Some(9))).unwrap_err();
+        match RafError::from(err) {
+            RafError::SlotOverflow { offset: 8, limit: 9 } => (),
+            other => panic!("unexpected {:?}", other)
+        }
+        assert_eq!(raf.len().unwrap(), 16);
+
+        let mut writer = raf.writer_at(30, None);
+        value.serialize(&mut writer).unwrap();
+        assert_eq!(writer.written(), 14);
+        assert_eq!(raf.len().unwrap(), 44);
+    }
+}
@@ -0,0 +1,168 @@
+use std::cmp;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// A `RandomAccessFile` that reads ahead: a small `read_at` that misses its window reads
+/// `read_ahead` bytes from the inner file, and later reads that fall inside them are served from
+/// memory. This turns the element-at-a-time reads of deserializing something like a large
+/// `Vec<T>` into a few large reads. Reads at least as large as the window go straight to the
+/// inner file.
+///
+/// Writes go straight through to the inner file and update the window where they overlap it. The
+/// inner file must not be changed through other handles while it is wrapped, or the window may
+/// serve stale data.
+#[derive(Debug)]
+pub struct ReadAheadRaf<R: RandomAccessFile> {
+    inner: R,
+    read_ahead: usize,
+    window_start: u64,
+    window: Vec<u8>,
+}
+
+impl<R: RandomAccessFile> ReadAheadRaf<R> {
+    pub fn new(inner: R, read_ahead: usize) -> ReadAheadRaf<R> {
+        ReadAheadRaf { inner, read_ahead, window_start: 0, window: Vec::new() }
+    }
+
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead
+    }
+
+    /// Changes the read-ahead size from the next read that misses the window on. 0 disables it.
+    pub fn set_read_ahead(&mut self, read_ahead: usize) {
+        self.read_ahead = read_ahead;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn window_end(&self) -> u64 {
+        self.window_start + self.window.len() as u64
+    }
+
+    // Copies the part of a write of `data` at `at` that overlaps the window into it.
+    fn update_window(&mut self, at: u64, data: &[u8]) {
+        let from = cmp::max(at, self.window_start);
+        let to = cmp::min(at + data.len() as u64, self.window_end());
+        if from < to {
+            self.window[(from - self.window_start) as usize..(to - self.window_start) as usize]
+                .copy_from_slice(&data[(from - at) as usize..(to - at) as usize]);
+        }
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for ReadAheadRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        if dat.is_empty() {
+            return Ok(0);
+        }
+        if at < self.window_start || at >= self.window_end() {
+            if dat.len() >= self.read_ahead {
+                return self.inner.read_at(at, dat);
+            }
+            self.window.clear();
+            self.window.resize(self.read_ahead, 0);
+            let read = match self.inner.read_at(at, &mut self.window) {
+                Ok(read) => read,
+                Err(e) => {
+                    self.window.clear();
+                    return Err(e);
+                }
+            };
+            self.window.truncate(read);
+            self.window_start = at;
+            if read == 0 {
+                return Ok(0);
+            }
+        }
+        let offset = (at - self.window_start) as usize;
+        let n = cmp::min(dat.len(), self.window.len() - offset);
+        dat[..n].copy_from_slice(&self.window[offset..offset + n]);
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let written = self.inner.write_at(at, data)?;
+        self.update_window(at, &data[..written]);
+        Ok(written)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.inner.append(data)?;
+        self.update_window(at, data);
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.window.clear();
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.window.clear();
+        self.inner.punch_hole(at, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use read_ahead::ReadAheadRaf;
+    use test_util::Counting;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use Serialize;
+
+    #[test]
+    fn serves_small_reads_from_the_window() {
+        let mut encoded = Vec::new();
+        (0..100u32).collect::<Vec<u32>>().serialize(&mut encoded).unwrap();
+        let inner = Counting::new(MemoryRandomAccessFile::from_vec(encoded));
+        let mut raf = ReadAheadRaf::new(inner, 256);
+
+        let len = raf.len().unwrap();
+        let values = Vec::<u32>::deserialize(&mut raf.reader_at(0, len)).unwrap();
+        assert_eq!(values, (0..100).collect::<Vec<u32>>());
+        assert_eq!(raf.get_ref().reads, 2);
+
+        raf.write_all_at(8, &7u32.to_le_bytes()).unwrap();
+        let mut first = [0u8; 4];
+        raf.read_exact_at(8, &mut first).unwrap();
+        assert_eq!(u32::from_le_bytes(first), 7);
+        assert_eq!(raf.get_ref().reads, 3);
+
+        raf.set_read_ahead(0);
+        raf.set_len(4).unwrap();
+        assert_eq!(raf.read_at(0, &mut [0u8; 8]).unwrap(), 4);
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(feature = "cfile")]
+use backend::cfile::utf8_path;
+#[cfg(feature = "cfile")]
+use cfile_rs::CFile;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Error;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use RafError;
+use RandomAccessFile;
+
+/// A handle that only exposes the read operations of a `RandomAccessFile`. Functions that take a
+/// `&mut ReadOnlyRaf<R>` are guaranteed by the type system not to modify the file.
+///
+/// It is not a `RandomAccessFile`, so `RafOptions::open` can't produce one; `RafOptions::read_only`
+/// only makes writes fail at run time.
+#[derive(Debug)]
+pub struct ReadOnlyRaf<R: RandomAccessFile> {
+    inner: R,
+}
+
+impl<R: RandomAccessFile> ReadOnlyRaf<R> {
+    /// Wraps an already opened file, giving up write access to it.
+    pub fn wrap(inner: R) -> ReadOnlyRaf<R> {
+        ReadOnlyRaf { inner }
+    }
+
+    /// Another read only handle to the same file, see `RandomAccessFile::try_clone`.
+    pub fn try_clone(&self) -> Result<ReadOnlyRaf<R>, RafError> {
+        Ok(ReadOnlyRaf::wrap(self.inner.try_clone()?))
+    }
+
+    pub fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.inner.read_at(at, dat)
+    }
+
+    pub fn at(&mut self, index: u64) -> Result<u8, RafError> {
+        self.inner.at(index)
+    }
+
+    pub fn read_exact_at(&mut self, at: u64, dat: &mut [u8]) -> Result<(), RafError> {
+        self.inner.read_exact_at(at, dat)
+    }
+
+    pub fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&mut self) -> Result<bool, RafError> {
+        self.inner.is_empty()
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl ReadOnlyRaf<File> {
+    /// Opens an existing file in read only mode.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ReadOnlyRaf<File>, RafError> {
+        let path = path.as_ref();
+        match OpenOptions::new().read(true).open(path) {
+            Ok(file) => Ok(ReadOnlyRaf::wrap(file)),
+            Err(e) => Err(RafError::from(e).with_path(path))
+        }
+    }
+}
+
+#[cfg(feature = "cfile")]
+impl ReadOnlyRaf<CFile> {
+    /// Opens an existing file in read only mode.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ReadOnlyRaf<CFile>, RafError> {
+        let path = utf8_path(path.as_ref())?;
+        match CFile::open(path, "r") {
+            Ok(file) => Ok(ReadOnlyRaf::wrap(file)),
+            Err(e) => Err(RafError::from(e).with_path(path))
+        }
+    }
+}
+
+impl<R: RandomAccessFile + Read> Read for ReadOnlyRaf<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: RandomAccessFile + Seek> Seek for ReadOnlyRaf<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ReadOnlyRaf;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use Serialize;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+
+    #[test]
+    fn reads_what_was_written() {
+        let path = env::temp_dir().join("raf_read_only.bin");
+        let path = path.to_str().unwrap();
+        {
+            let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+            raf.write_at(0, &[1, 2, 3]).unwrap();
+        }
+        let mut raf = ReadOnlyRaf::<File>::open(path).unwrap();
+        assert_eq!(u8::deserialize(&mut raf).unwrap(), 1);
+        assert_eq!(raf.at(2).unwrap(), 3);
+        let _ = fs::remove_file(path);
+    }
+}
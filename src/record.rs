@@ -0,0 +1,228 @@
+use checksum::Crc32;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+use Serialize;
+
+const READ: u8 = 0;
+const WRITE: u8 = 1;
+const APPEND: u8 = 2;
+const SET_LEN: u8 = 3;
+const FLUSH: u8 = 4;
+const SYNC_DATA: u8 = 5;
+const SYNC_ALL: u8 = 6;
+const ALLOCATE: u8 = 7;
+const PUNCH_HOLE: u8 = 8;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// A `RandomAccessFile` that logs every successful operation to a side log, so that a sequence of
+/// operations that corrupted a file can be reproduced with `replay`. Each record holds the
+/// operation, its offset and length and a CRC-32 of the data transferred; writes and appends also
+/// hold the data itself, which replaying needs. Reads are only logged by their checksum, which
+/// `replay` compares against.
+///
+/// Records are serialized with `Serialize`, a byte for the operation followed by the `u64` offset,
+/// the `u64` length, the `u32` checksum and then any data. `flush` also flushes the log. Failed
+/// operations, and ones that don't touch the data such as `len`, are not logged.
+#[derive(Debug)]
+pub struct RecordingRaf<R: RandomAccessFile, W: Write> {
+    inner: R,
+    log: W,
+}
+
+impl<R: RandomAccessFile, W: Write> RecordingRaf<R, W> {
+    pub fn new(inner: R, log: W) -> RecordingRaf<R, W> {
+        RecordingRaf { inner, log }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// The file and the log.
+    pub fn into_parts(self) -> (R, W) {
+        (self.inner, self.log)
+    }
+
+    fn record(&mut self, op: u8, at: u64, len: u64, data: &[u8], with_data: bool) -> Result<(), RafError> {
+        op.serialize(&mut self.log)?;
+        at.serialize(&mut self.log)?;
+        len.serialize(&mut self.log)?;
+        crc32(data).serialize(&mut self.log)?;
+        if with_data {
+            self.log.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: RandomAccessFile, W: Write> RandomAccessFile for RecordingRaf<R, W> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let n = self.inner.read_at(at, dat)?;
+        self.record(READ, at, n as u64, &dat[..n], false)?;
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let n = self.inner.write_at(at, data)?;
+        self.record(WRITE, at, n as u64, &data[..n], true)?;
+        Ok(n)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.inner.append(data)?;
+        self.record(APPEND, at, data.len() as u64, data, true)?;
+        Ok(at)
+    }
+
+    /// Logged as a write if it wrote `new`.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        let written = self.inner.compare_and_write_at(at, expected, new)?;
+        if written {
+            self.record(WRITE, at, new.len() as u64, new, true)?;
+        }
+        Ok(written)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)?;
+        self.record(SET_LEN, 0, len, &[], false)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()?;
+        self.record(FLUSH, 0, 0, &[], false)?;
+        self.log.flush().map_err(RafError::from)
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()?;
+        self.record(SYNC_DATA, 0, 0, &[], false)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()?;
+        self.record(SYNC_ALL, 0, 0, &[], false)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)?;
+        self.record(ALLOCATE, 0, len, &[], false)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)?;
+        self.record(PUNCH_HOLE, at, len, &[], false)
+    }
+}
+
+/// Re-executes a log written by a `RecordingRaf` against `raf`, normally a fresh, empty file,
+/// returning the number of operations replayed. Appends are replayed as writes at the offset they
+/// were recorded at, so the file ends up laid out the same. Each recorded read is repeated and
+/// fails the replay with `ErrorKind::InvalidData` if it doesn't return the same data, which
+/// points at the first operation whose outcome differs from the recording.
+pub fn replay<R: RandomAccessFile>(log: &mut dyn Read, raf: &mut R) -> Result<u64, RafError> {
+    let mut count = 0;
+    loop {
+        let mut op = [0u8];
+        if log.read(&mut op)? == 0 {
+            return Ok(count);
+        }
+        let at = u64::deserialize(log)?;
+        let len = u64::deserialize(log)?;
+        let crc = u32::deserialize(log)?;
+        match op[0] {
+            READ => {
+                // Checked against the length first, so a corrupt record can't ask for a huge buffer.
+                let file_len = raf.len()?;
+                let matches = at.checked_add(len).is_some_and(|end| end <= file_len) && {
+                    let mut buf = vec![0u8; len as usize];
+                    raf.read_exact_at(at, &mut buf)?;
+                    crc32(&buf) == crc
+                };
+                if !matches {
+                    let message = format!("operation {}, a read of {} bytes at {}, differs from the recording", count, len, at);
+                    return Err(Error::new(ErrorKind::InvalidData, message).into());
+                }
+            },
+            WRITE | APPEND => {
+                let mut data = Vec::new();
+                if log.take(len).read_to_end(&mut data)? as u64 != len {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "the log ends part way through a write").into());
+                }
+                raf.write_all_at(at, &data)?;
+            },
+            SET_LEN => raf.set_len(len)?,
+            FLUSH => raf.flush()?,
+            SYNC_DATA => raf.sync_data()?,
+            SYNC_ALL => raf.sync_all()?,
+            ALLOCATE => raf.allocate(len)?,
+            PUNCH_HOLE => raf.punch_hole(at, len)?,
+            op => return Err(Error::new(ErrorKind::InvalidData, format!("unknown operation {} in the log", op)).into())
+        }
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use record::{replay, RecordingRaf};
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn replays_a_recording() {
+        let mut raf = RecordingRaf::new(MemoryRandomAccessFile::new(), Vec::new());
+        raf.append(b"recorded").unwrap();
+        raf.write_all_at(4, b"ding, replayed").unwrap();
+        let mut buf = [0u8; 4];
+        raf.read_exact_at(0, &mut buf).unwrap();
+        raf.set_len(12).unwrap();
+        assert!(raf.compare_and_write_at(8, b", re", b"; RE").unwrap());
+        assert!(!raf.compare_and_write_at(8, b", re", b"lost").unwrap());
+        raf.flush().unwrap();
+        let (mut original, log) = raf.into_parts();
+
+        let mut copy = MemoryRandomAccessFile::new();
+        assert_eq!(replay(&mut &log[..], &mut copy).unwrap(), 6);
+        let mut expected = [0u8; 12];
+        original.read_exact_at(0, &mut expected).unwrap();
+        let mut replayed = [0u8; 12];
+        copy.read_exact_at(0, &mut replayed).unwrap();
+        assert_eq!(replayed, expected);
+
+        // Corrupting the appended data, which follows the first 21 byte header, makes the read
+        // return something else.
+        let mut log = log;
+        log[21] ^= 1;
+        let err = replay(&mut &log[..], &mut MemoryRandomAccessFile::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
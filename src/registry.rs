@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+// Canonical paths of every file currently held by an `ExclusiveRaf` in this process.
+static WRITERS: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+fn with_writers<T, F: FnOnce(&mut HashSet<PathBuf>) -> T>(f: F) -> T {
+    let mut guard = match WRITERS.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner()
+    };
+    f(guard.get_or_insert_with(HashSet::new))
+}
+
+// The canonical path of `path`, or for a file that doesn't exist yet, the canonical path of its
+// directory joined with its name.
+fn registry_key(path: &Path) -> Result<PathBuf, Error> {
+    match fs::canonicalize(path) {
+        Ok(key) => Ok(key),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => {
+            let name = path.file_name()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "the path doesn't name a file"))?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new(".")
+            };
+            Ok(fs::canonicalize(dir)?.join(name))
+        },
+        Err(e) => Err(e)
+    }
+}
+
+/// Returns true if some `ExclusiveRaf` in this process currently has `path` open.
+pub fn is_open_exclusive<P: AsRef<Path>>(path: P) -> bool {
+    match registry_key(path.as_ref()) {
+        Ok(key) => with_writers(|writers| writers.contains(&key)),
+        Err(_) => false
+    }
+}
+
+/// A writable handle that is registered, by canonical path, in a process wide registry. At most
+/// one `ExclusiveRaf` can exist for a given file at a time; opening a second one fails with an
+/// error of kind `ResourceBusy` rather than letting two writers silently corrupt each other.
+/// The path is registered before the file is opened, so a rejected open never touches the file,
+/// even with `truncate` set. The registration is released when the handle is dropped.
+///
+/// Files are told apart by path, so two hard links to the same file are two different files to
+/// the registry.
+///
+/// Read only handles (`ReadOnlyRaf`) are not registered and can be opened alongside a writer.
+#[derive(Debug)]
+pub struct ExclusiveRaf<R: RandomAccessFile> {
+    inner: R,
+    key: PathBuf,
+}
+
+impl<R: OpenRandomAccessFile> ExclusiveRaf<R> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ExclusiveRaf<R>, RafError> {
+        OpenRandomAccessFile::new(path)
+    }
+}
+
+impl<R: OpenRandomAccessFile> OpenRandomAccessFile for ExclusiveRaf<R> {
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<ExclusiveRaf<R>, RafError> {
+        let path = path.as_ref();
+        let key = registry_key(path).map_err(|e| RafError::from(e).with_path(path))?;
+        let registered = with_writers(|writers| writers.insert(key.clone()));
+        if !registered {
+            return Err(Error::new(ErrorKind::ResourceBusy,
+                                  format!("{} is already open for writing in this process", key.display())).into());
+        }
+        match R::open_with(path, options) {
+            Ok(inner) => Ok(ExclusiveRaf { inner, key }),
+            Err(e) => {
+                with_writers(|writers| writers.remove(&key));
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<R: RandomAccessFile> ExclusiveRaf<R> {
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: RandomAccessFile> Drop for ExclusiveRaf<R> {
+    fn drop(&mut self) {
+        let key = &self.key;
+        with_writers(|writers| writers.remove(key));
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for ExclusiveRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.inner.read_at(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.inner.write_at(at, data)
+    }
+
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        self.inner.read_at_vectored(at, bufs)
+    }
+
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        self.inner.write_at_vectored(at, bufs)
+    }
+
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.inner.append(data)
+    }
+
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        self.inner.append_vectored(bufs)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+}
+
+impl<R: RandomAccessFile + Read> Read for ExclusiveRaf<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: RandomAccessFile + Write> Write for ExclusiveRaf<R> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Write::flush(&mut self.inner)
+    }
+}
+
+impl<R: RandomAccessFile + Seek> Seek for ExclusiveRaf<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use registry::is_open_exclusive;
+    use registry::ExclusiveRaf;
+    use RafOptions;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn second_writer_is_rejected() {
+        let path = env::temp_dir().join("raf_registry.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let mut first = ExclusiveRaf::<File>::open(path).unwrap();
+            assert!(is_open_exclusive(path));
+            first.write_all_at(0, b"kept").unwrap();
+            let second = RafOptions::new().truncate(true).open::<ExclusiveRaf<File>, _>(path);
+            assert_eq!(second.unwrap_err().kind(), ErrorKind::ResourceBusy);
+            assert_eq!(fs::read(path).unwrap(), b"kept");
+        }
+        assert!(!is_open_exclusive(path));
+        ExclusiveRaf::<File>::open(path).unwrap();
+
+        // A failed open releases the registration it took.
+        let missing = env::temp_dir().join("raf_registry_missing.bin");
+        let _ = fs::remove_file(&missing);
+        let err = RafOptions::new().open::<ExclusiveRaf<File>, _>(&missing).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!is_open_exclusive(&missing));
+        let _ = fs::remove_file(path);
+    }
+}
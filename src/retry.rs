@@ -0,0 +1,302 @@
+use std::cmp;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use std::path::Path;
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+
+/// Controls how `RetryingRaf` retries failed operations.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts per operation, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The delay between attempts is doubled after every retry, up to this limit.
+    pub max_backoff: Duration,
+    /// If set, each delay is chosen uniformly at random between zero and the computed backoff, so
+    /// that many handles failing at once don't retry in lockstep.
+    pub jitter: bool,
+    /// The total number of retries the handle may perform over its lifetime, across all
+    /// operations. Once it is used up, errors are returned immediately. `None` means unlimited.
+    pub retry_budget: Option<u64>,
+    /// Decides which errors are worth retrying; `is_transient` by default. A network file system
+    /// that reports, say, `ESTALE` for a handle that recovers on its own can be given a classifier
+    /// that also accepts that.
+    pub is_retryable: fn(&RafError) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            jitter: true,
+            retry_budget: None,
+            is_retryable: is_transient,
+        }
+    }
+}
+
+/// Returns true for errors that are worth retrying: interrupted system calls, `EAGAIN` and timeouts.
+pub fn is_transient(e: &RafError) -> bool {
+    matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// A wrapper around a `RandomAccessFile` that retries `read_at` and `write_at` when they fail with
+/// an error the `RetryPolicy` classifies as retryable (by default a transient one, see
+/// `is_transient`), sleeping according to the policy in between. Any other error is returned
+/// immediately.
+///
+/// `append` is passed through without retrying: a failed append may already have written part of
+/// its data, and repeating it would duplicate that part.
+#[derive(Debug)]
+pub struct RetryingRaf<R: RandomAccessFile> {
+    inner: R,
+    policy: RetryPolicy,
+    retries_left: Option<u64>,
+    rng: u64,
+}
+
+impl<R: RandomAccessFile> RetryingRaf<R> {
+    pub fn wrap(inner: R, policy: RetryPolicy) -> RetryingRaf<R> {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as u64).unwrap_or(0);
+        RetryingRaf {
+            inner,
+            retries_left: policy.retry_budget,
+            policy,
+            rng: seed | 1,
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// The number of retries left in the budget, or `None` if the budget is unlimited.
+    pub fn retries_left(&self) -> Option<u64> {
+        self.retries_left
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn retry<T, F>(&mut self, mut op: F) -> Result<T, RafError>
+        where F: FnMut(&mut R) -> Result<T, RafError> {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match op(&mut self.inner) {
+                Ok(x) => return Ok(x),
+                Err(e) => {
+                    if !(self.policy.is_retryable)(&e) || attempt >= self.policy.max_attempts || self.retries_left == Some(0) {
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(ref mut left) = self.retries_left {
+                *left -= 1;
+            }
+            let delay = if self.policy.jitter {
+                let nanos = cmp::min(backoff.as_nanos(), u64::MAX as u128) as u64;
+                Duration::from_nanos(self.next_random() % nanos.saturating_add(1))
+            } else {
+                backoff
+            };
+            thread::sleep(delay);
+            backoff = next_backoff(backoff, self.policy.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+// Doubles `backoff`, up to `max`, without overflowing however large they are.
+fn next_backoff(backoff: Duration, max: Duration) -> Duration {
+    backoff.checked_mul(2).map_or(max, |doubled| cmp::min(doubled, max))
+}
+
+impl<R: OpenRandomAccessFile> OpenRandomAccessFile for RetryingRaf<R> {
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<RetryingRaf<R>, RafError> {
+        Ok(RetryingRaf::wrap(R::open_with(path, options)?, RetryPolicy::default()))
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for RetryingRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.retry(|inner| inner.read_at(at, dat))
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.retry(|inner| inner.write_at(at, data))
+    }
+
+    fn read_at_vectored(&mut self, at: u64, bufs: &mut [IoSliceMut]) -> Result<usize, RafError> {
+        self.retry(|inner| inner.read_at_vectored(at, bufs))
+    }
+
+    fn write_at_vectored(&mut self, at: u64, bufs: &[IoSlice]) -> Result<usize, RafError> {
+        self.retry(|inner| inner.write_at_vectored(at, bufs))
+    }
+
+    /// Clones the inner handle, wrapping it with the same policy and a fresh retry budget.
+    fn try_clone(&self) -> Result<RetryingRaf<R>, RafError> {
+        Ok(RetryingRaf::wrap(self.inner.try_clone()?, self.policy.clone()))
+    }
+
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.inner.append(data)
+    }
+
+    fn append_vectored(&mut self, bufs: &[IoSlice]) -> Result<u64, RafError> {
+        self.inner.append_vectored(bufs)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.retry(|inner| inner.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.retry(|inner| inner.set_len(len))
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.retry(|inner| inner.flush())
+    }
+
+    // Not retried: after a failed fsync the kernel may already have dropped the dirty pages, so a
+    // second attempt can report success without the data having been written.
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use retry::RetryPolicy;
+    use retry::RetryingRaf;
+    use test_util::Counting;
+    use MemoryRandomAccessFile;
+    use RafError;
+    use RandomAccessFile;
+    use std::io::Error;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    // Fails the first `failures` reads with `kind`, then reads zeros.
+    fn flaky(failures: u32, kind: ErrorKind) -> Counting {
+        let mut failures = failures;
+        Counting::new(MemoryRandomAccessFile::from_vec(vec![0u8; 4])).before_read(move |_| {
+            if failures == 0 {
+                return Ok(());
+            }
+            failures -= 1;
+            Err(Error::new(kind, "flaky").into())
+        })
+    }
+
+    fn policy(budget: Option<u64>) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            jitter: true,
+            retry_budget: budget,
+            is_retryable: ::retry::is_transient,
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let inner = flaky(2, ErrorKind::Interrupted);
+        let mut raf = RetryingRaf::wrap(inner, policy(None));
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap(), 4);
+
+        let inner = flaky(3, ErrorKind::WouldBlock);
+        let mut raf = RetryingRaf::wrap(inner, policy(None));
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn permanent_errors_and_budget() {
+        let inner = flaky(1, ErrorKind::NotFound);
+        let mut raf = RetryingRaf::wrap(inner, policy(None));
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap_err().kind(), ErrorKind::NotFound);
+
+        let inner = flaky(2, ErrorKind::TimedOut);
+        let mut raf = RetryingRaf::wrap(inner, policy(Some(1)));
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(raf.retries_left(), Some(0));
+    }
+
+    #[test]
+    fn backoff_saturates() {
+        let max = Duration::MAX;
+        assert_eq!(::retry::next_backoff(Duration::from_millis(3), max), Duration::from_millis(6));
+        assert_eq!(::retry::next_backoff(Duration::from_secs(u64::MAX / 2 + 1), max), max);
+        assert_eq!(::retry::next_backoff(Duration::from_secs(5), Duration::from_secs(8)), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn custom_classifier() {
+        fn not_found_too(e: &RafError) -> bool {
+            ::retry::is_transient(e) || e.kind() == ErrorKind::NotFound
+        }
+        let retry_not_found = RetryPolicy { is_retryable: not_found_too, ..policy(None) };
+        let inner = flaky(2, ErrorKind::NotFound);
+        let mut raf = RetryingRaf::wrap(inner, retry_not_found);
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap(), 4);
+
+        let never = RetryPolicy { is_retryable: |_| false, ..policy(None) };
+        let inner = flaky(1, ErrorKind::Interrupted);
+        let mut raf = RetryingRaf::wrap(inner, never);
+        assert_eq!(raf.read_at(0, &mut [0u8; 4]).unwrap_err().kind(), ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use RandomAccessFile;
+use Serialize;
+
+/// Serializes `value` and spreads its encoding over `regions`, a list of `(offset, len)` pairs that
+/// are filled in order. Nothing is written if the encoding does not fit in the combined length of
+/// the regions. Returns the number of bytes of the encoding, which may be less than the space
+/// supplied; the tail of the last used region is left untouched.
+pub fn serialize_scattered<T, R>(value: &T, raf: &mut R, regions: &[(u64, u64)]) -> Result<usize, Error>
+    where T: Serialize, R: RandomAccessFile {
+    let mut encoded = Vec::new();
+    value.serialize(&mut encoded)?;
+    let capacity = regions.iter().fold(0u64, |acc, &(_, len)| acc.saturating_add(len));
+    if encoded.len() as u64 > capacity {
+        return Err(Error::new(ErrorKind::InvalidInput, "serialized value does not fit in the supplied regions"));
+    }
+
+    let mut rest = &encoded[..];
+    for &(offset, len) in regions {
+        if rest.is_empty() {
+            break;
+        }
+        let take = ::std::cmp::min(len, rest.len() as u64) as usize;
+        raf.write_all_at(offset, &rest[..take])?;
+        rest = &rest[take..];
+    }
+    Ok(encoded.len())
+}
+
+/// Reads a value written by `serialize_scattered` back from the same list of regions.
+pub fn deserialize_gathered<T, R>(raf: &mut R, regions: &[(u64, u64)]) -> Result<T::DeserializeOutput, Error>
+    where T: Serialize, R: RandomAccessFile {
+    T::deserialize(&mut GatherReader::new(raf, regions))
+}
+
+/// A `Read` implementation that reads the regions of a file one after another, as if they were
+/// one contiguous stream. Data is fetched on demand, so only the regions actually consumed are
+/// read.
+pub struct GatherReader<'a, R: RandomAccessFile + 'a> {
+    raf: &'a mut R,
+    regions: &'a [(u64, u64)],
+    // Bytes already consumed from regions[0].
+    pos: u64,
+}
+
+impl<'a, R: RandomAccessFile + 'a> GatherReader<'a, R> {
+    pub fn new(raf: &'a mut R, regions: &'a [(u64, u64)]) -> GatherReader<'a, R> {
+        GatherReader { raf, regions, pos: 0 }
+    }
+}
+
+impl<'a, R: RandomAccessFile + 'a> Read for GatherReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while let Some(&(offset, len)) = self.regions.first() {
+            if self.pos < len {
+                let want = ::std::cmp::min(len - self.pos, buf.len() as u64) as usize;
+                let read = self.raf.read_at(offset + self.pos, &mut buf[..want])?;
+                self.pos += read as u64;
+                return Ok(read);
+            }
+            self.regions = &self.regions[1..];
+            self.pos = 0;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scatter::deserialize_gathered;
+    use scatter::serialize_scattered;
+    use OpenRandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+
+    #[test]
+    fn scatter_and_gather() {
+        let path = env::temp_dir().join("raf_scatter.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        let regions = [(100, 5), (10, 7), (50, 64)];
+        let value: Vec<u32> = (0..5).collect();
+
+        assert_eq!(serialize_scattered(&value, &mut raf, &regions).unwrap(), 28);
+        assert_eq!(deserialize_gathered::<Vec<u32>, _>(&mut raf, &regions).unwrap(), value);
+        assert!(serialize_scattered(&value, &mut raf, &regions[..2]).is_err());
+        let _ = fs::remove_file(path);
+    }
+}
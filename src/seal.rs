@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use RafError;
+use RandomAccessFile;
+use ReadOnlyRaf;
+use Serialize;
+
+static SEAL_MAGIC: u64 = 0x4c41_4553_4641_5221; // "!RAFSEAL"
+static FOOTER_SIZE: usize = 8 + 8 + 8 + 4;
+
+/// The footer that `seal` appends to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealFooter {
+    /// The number of records the writer reported when sealing; not interpreted by this crate.
+    pub record_count: u64,
+    /// The length of the data preceding the footer.
+    pub len: u64,
+    /// CRC-32 of the data preceding the footer.
+    pub checksum: u32,
+}
+
+/// Finalizes a file: appends a footer holding `record_count`, the length of the file and a
+/// checksum of its entire contents, syncs it to disk, and hands back a read only handle.
+pub fn seal<R: RandomAccessFile>(mut raf: R, record_count: u64) -> Result<(ReadOnlyRaf<R>, SealFooter), RafError> {
+    let len = raf.len()?;
+    let footer = SealFooter {
+        record_count,
+        len,
+        checksum: raf.checksum_range(0, len)?,
+    };
+    let mut buf = Vec::with_capacity(FOOTER_SIZE);
+    SEAL_MAGIC.serialize(&mut buf)?;
+    footer.record_count.serialize(&mut buf)?;
+    footer.len.serialize(&mut buf)?;
+    footer.checksum.serialize(&mut buf)?;
+    raf.append(&buf)?;
+    raf.sync_all()?;
+    Ok((ReadOnlyRaf::wrap(raf), footer))
+}
+
+/// Reads the footer written by `seal` without verifying the checksum of the data it covers.
+/// Returns an error of kind `InvalidData` if the file does not end in a seal footer.
+pub fn read_seal_footer<R: RandomAccessFile>(raf: &mut R) -> Result<SealFooter, RafError> {
+    let total = raf.len()?;
+    if total < FOOTER_SIZE as u64 {
+        return Err(Error::new(ErrorKind::InvalidData, "file is too short to have been sealed").into());
+    }
+    let mut buf = vec![0u8; FOOTER_SIZE];
+    let data_len = total - FOOTER_SIZE as u64;
+    raf.read_exact_at(data_len, &mut buf)?;
+    let mut reader = &buf[..];
+    if u64::deserialize(&mut reader)? != SEAL_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "file was never sealed").into());
+    }
+    let footer = SealFooter {
+        record_count: u64::deserialize(&mut reader)?,
+        len: u64::deserialize(&mut reader)?,
+        checksum: u32::deserialize(&mut reader)?,
+    };
+    if footer.len != data_len {
+        return Err(Error::new(ErrorKind::InvalidData, "sealed length does not match the file length").into());
+    }
+    Ok(footer)
+}
+
+/// Opens a file produced by `seal` in read only mode, verifying it with `verify_sealed`.
+pub fn open_sealed<P: AsRef<Path>>(path: P) -> Result<(ReadOnlyRaf<File>, SealFooter), RafError> {
+    let path = path.as_ref();
+    let file = ReadOnlyRaf::<File>::open(path)?.into_inner();
+    verify_sealed(file).map_err(|e| e.with_path(path))
+}
+
+/// Verifies the footer and checksum of a file produced by `seal`, and hands back a read only
+/// handle to it. Files that were never sealed, were modified afterwards, or are truncated are
+/// rejected with an error of kind `InvalidData`.
+pub fn verify_sealed<R: RandomAccessFile>(mut raf: R) -> Result<(ReadOnlyRaf<R>, SealFooter), RafError> {
+    let footer = read_seal_footer(&mut raf)?;
+    if !raf.verify_range(0, footer.len, footer.checksum)? {
+        return Err(Error::new(ErrorKind::InvalidData, "sealed file failed checksum verification").into());
+    }
+    Ok((ReadOnlyRaf::wrap(raf), footer))
+}
+
+#[cfg(test)]
+mod tests {
+    use seal::open_sealed;
+    use seal::seal;
+    use seal::verify_sealed;
+    use MemoryRandomAccessFile;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn seal_and_verify() {
+        let path = env::temp_dir().join("raf_seal.bin");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        raf.append(b"some records").unwrap();
+        let (_, footer) = seal(raf, 2).unwrap();
+
+        let (mut sealed, opened) = open_sealed(path).unwrap();
+        assert_eq!(footer, opened);
+        assert_eq!(opened.len, 12);
+        assert_eq!(sealed.at(0).unwrap(), b's');
+
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        raf.write_at(0, b"S").unwrap();
+        assert_eq!(open_sealed(path).unwrap_err().kind(), ErrorKind::InvalidData);
+        let _ = fs::remove_file(path);
+
+        let (sealed, footer) = seal(MemoryRandomAccessFile::from_vec(b"in memory".to_vec()), 1).unwrap();
+        let (_, verified) = verify_sealed(sealed.into_inner()).unwrap();
+        assert_eq!(verified, footer);
+    }
+
+    #[test]
+    fn rejects_unsealed() {
+        let path = env::temp_dir().join("raf_unsealed.bin");
+        let path = path.to_str().unwrap();
+        let mut raf: File = OpenRandomAccessFile::new(path).unwrap();
+        raf.write_at(0, &[0u8; 64]).unwrap();
+        assert_eq!(open_sealed(path).unwrap_err().kind(), ErrorKind::InvalidData);
+        let _ = fs::remove_file(path);
+    }
+}
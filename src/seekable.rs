@@ -0,0 +1,233 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use RafError;
+use RandomAccessFile;
+
+/// Adapts any `Read + Write + Seek` type (a `Cursor`, a `File`, a custom stream) into a
+/// `RandomAccessFile` by seeking before reads and writes. This moves the stream's cursor, so
+/// interleaved `Read`/`Write` calls on the adapter continue from wherever the last positioned
+/// operation ended.
+///
+/// The adapter keeps track of the cursor and skips the seek when it is already at the requested
+/// offset, so sequential reads or writes cost no seeks at all. It still seeks when switching
+/// between reading and writing, which C's stdio requires, so this is also the way to cut down on
+/// the seeks of a `CFile`.
+#[derive(Debug, Clone, Default)]
+pub struct SeekableRaf<T: Read + Write + Seek> {
+    inner: T,
+    // Where the stream's cursor is, if known.
+    pos: Option<u64>,
+    // The direction of the last transfer since the last seek or flush.
+    last: Option<Direction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+impl<T: Read + Write + Seek> SeekableRaf<T> {
+    pub fn new(inner: T) -> SeekableRaf<T> {
+        SeekableRaf { inner, pos: None, last: None }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// The stream may be moved through the returned reference, so the next positioned operation
+    /// always seeks.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.forget_position();
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn forget_position(&mut self) {
+        self.pos = None;
+        self.last = None;
+    }
+
+    fn seek_to(&mut self, at: u64, direction: Direction) -> Result<(), Error> {
+        if self.pos == Some(at) && self.last.is_none_or(|last| last == direction) {
+            return Ok(());
+        }
+        self.forget_position();
+        self.inner.seek(SeekFrom::Start(at))?;
+        self.pos = Some(at);
+        Ok(())
+    }
+
+    // Records the outcome of a transfer that started at the cursor.
+    fn moved<R>(&mut self, result: Result<usize, R>, direction: Direction) -> Result<usize, R> {
+        match result {
+            Ok(n) => {
+                self.pos = self.pos.map(|pos| pos + n as u64);
+                self.last = Some(direction);
+            },
+            Err(_) => self.forget_position()
+        }
+        result
+    }
+}
+
+impl<T: Read + Write + Seek> RandomAccessFile for SeekableRaf<T> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.seek_to(at, Direction::Read)?;
+        let read = self.inner.read(dat);
+        self.moved(read, Direction::Read).map_err(RafError::from)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.seek_to(at, Direction::Write)?;
+        let written = self.inner.write(data);
+        self.moved(written, Direction::Write).map_err(RafError::from)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.forget_position();
+        let at = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.write_all(data)?;
+        self.pos = Some(at + data.len() as u64);
+        self.last = Some(Direction::Write);
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.forget_position();
+        let pos = self.inner.stream_position()?;
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(pos))?;
+        self.pos = Some(pos);
+        Ok(len)
+    }
+
+    /// A generic stream can't be resized, so this always fails with `Unsupported`.
+    fn set_len(&mut self, _: u64) -> Result<(), RafError> {
+        Err(Error::new(ErrorKind::Unsupported, "set_len is not supported by a Read + Write + Seek stream").into())
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.last = None;
+        self.inner.flush().map_err(RafError::from)
+    }
+
+    /// A generic stream has no notion of durable storage, so this only flushes it.
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        RandomAccessFile::flush(self)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        RandomAccessFile::flush(self)
+    }
+}
+
+impl<T: Read + Write + Seek> Read for SeekableRaf<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.inner.read(buf);
+        self.moved(read, Direction::Read)
+    }
+}
+
+impl<T: Read + Write + Seek> Write for SeekableRaf<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.inner.write(buf);
+        self.moved(written, Direction::Write)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.last = None;
+        self.inner.flush()
+    }
+}
+
+impl<T: Read + Write + Seek> Seek for SeekableRaf<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        self.forget_position();
+        let pos = self.inner.seek(pos)?;
+        self.pos = Some(pos);
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use seekable::SeekableRaf;
+    use RandomAccessFile;
+    use std::io::Cursor;
+    use std::io::Error;
+    use std::io::Read;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    #[test]
+    fn cursor_as_random_access_file() {
+        let mut raf = SeekableRaf::new(Cursor::new(Vec::new()));
+        raf.write_at(2, b"xy").unwrap();
+        raf.append(b"z").unwrap();
+        assert_eq!(raf.get_ref().get_ref(), b"\0\0xyz");
+        assert_eq!(raf.at(3).unwrap(), b'y');
+        assert_eq!(raf.len().unwrap(), 5);
+    }
+
+    // Counts the seeks made on a cursor.
+    struct CountingSeeks {
+        inner: Cursor<Vec<u8>>,
+        seeks: usize,
+    }
+
+    impl Read for CountingSeeks {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingSeeks {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Seek for CountingSeeks {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+            self.seeks += 1;
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn sequential_transfers_skip_seeks() {
+        let mut raf = SeekableRaf::new(CountingSeeks { inner: Cursor::new(b"0123456789".to_vec()), seeks: 0 });
+        let mut buf = [0u8; 2];
+        for at in 0..5 {
+            raf.read_exact_at(at * 2, &mut buf).unwrap();
+        }
+        assert_eq!(raf.get_ref().seeks, 1);
+        raf.write_all_at(10, b"ab").unwrap();
+        raf.write_all_at(12, b"cd").unwrap();
+        assert_eq!(raf.get_ref().seeks, 2);
+        raf.read_exact_at(3, &mut buf).unwrap();
+        raf.read_exact_at(5, &mut buf).unwrap();
+        assert_eq!((raf.get_ref().seeks, &buf), (3, b"56"));
+
+        raf.seek(SeekFrom::Start(0)).unwrap();
+        raf.read_exact(&mut buf).unwrap();
+        raf.read_exact_at(2, &mut buf).unwrap();
+        assert_eq!((raf.get_ref().seeks, &buf), (4, b"23"));
+        raf.get_mut();
+        raf.read_exact_at(4, &mut buf).unwrap();
+        assert_eq!(raf.get_ref().seeks, 5);
+    }
+}
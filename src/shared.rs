@@ -0,0 +1,126 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::Arc;
+use Serialize;
+
+// Each shared value is prefixed by a u64 tag: NEW_VALUE if its encoding follows, or the id of an
+// earlier value plus one for a back-reference.
+static NEW_VALUE: u64 = 0;
+
+/// Serializes `Rc` and `Arc` values so that a value reachable through several pointers is only
+/// encoded once; later occurrences are written as back-references. Use one `SharedWriter` per
+/// serialization pass and read the result back with a `SharedReader`, calling the matching
+/// `deserialize_*` method in the same order.
+#[derive(Default)]
+pub struct SharedWriter {
+    ids: HashMap<*const (), u64>,
+    // Clones of everything written, so that no address is freed and reused during the pass.
+    keep_alive: Vec<Box<dyn Any>>,
+}
+
+impl SharedWriter {
+    pub fn new() -> SharedWriter {
+        SharedWriter::default()
+    }
+
+    fn serialize_ptr<T: Serialize>(&mut self, ptr: *const (), value: &T, keep: Box<dyn Any>,
+                                   to: &mut dyn Write) -> Result<(), Error> {
+        if let Some(&id) = self.ids.get(&ptr) {
+            return (id + 1).serialize(to);
+        }
+        NEW_VALUE.serialize(to)?;
+        value.serialize(to)?;
+        self.ids.insert(ptr, self.keep_alive.len() as u64);
+        self.keep_alive.push(keep);
+        Ok(())
+    }
+
+    pub fn serialize_rc<T: Serialize + 'static>(&mut self, value: &Rc<T>, to: &mut dyn Write) -> Result<(), Error> {
+        self.serialize_ptr(Rc::as_ptr(value) as *const (), &**value, Box::new(value.clone()), to)
+    }
+
+    pub fn serialize_arc<T: Serialize + 'static>(&mut self, value: &Arc<T>, to: &mut dyn Write) -> Result<(), Error> {
+        self.serialize_ptr(Arc::as_ptr(value) as *const (), &**value, Box::new(value.clone()), to)
+    }
+}
+
+/// The counterpart of `SharedWriter`: rebuilds the sharing between values on deserialization, so
+/// every back-reference yields a clone of the same `Rc`/`Arc`.
+#[derive(Default)]
+pub struct SharedReader {
+    values: Vec<Box<dyn Any>>,
+}
+
+impl SharedReader {
+    pub fn new() -> SharedReader {
+        SharedReader::default()
+    }
+
+    fn deserialize_ptr<P, F>(&mut self, from: &mut dyn Read, wrap: F) -> Result<P, Error>
+        where P: Clone + 'static, F: FnOnce(&mut dyn Read) -> Result<P, Error> {
+        let tag = u64::deserialize(from)?;
+        if tag == NEW_VALUE {
+            let ptr = wrap(from)?;
+            self.values.push(Box::new(ptr.clone()));
+            return Ok(ptr);
+        }
+        match self.values.get((tag - 1) as usize) {
+            Some(value) => match value.downcast_ref::<P>() {
+                Some(ptr) => Ok(ptr.clone()),
+                None => Err(Error::new(ErrorKind::InvalidData, "back-reference to a value of a different type"))
+            },
+            None => Err(Error::new(ErrorKind::InvalidData, "back-reference to a value that was never read"))
+        }
+    }
+
+    pub fn deserialize_rc<T>(&mut self, from: &mut dyn Read) -> Result<Rc<T>, Error>
+        where T: Serialize<DeserializeOutput = T> + 'static {
+        self.deserialize_ptr(from, |from| T::deserialize(from).map(Rc::new))
+    }
+
+    pub fn deserialize_arc<T>(&mut self, from: &mut dyn Read) -> Result<Arc<T>, Error>
+        where T: Serialize<DeserializeOutput = T> + 'static {
+        self.deserialize_ptr(from, |from| T::deserialize(from).map(Arc::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::SharedReader;
+    use shared::SharedWriter;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[test]
+    fn sharing_is_preserved() {
+        let a = Rc::new(String::from("shared"));
+        let b = Rc::new(String::from("other"));
+        let c = Arc::new(7u64);
+        let mut buf = Vec::new();
+        let mut writer = SharedWriter::new();
+        for rc in &[a.clone(), b.clone(), a.clone()] {
+            writer.serialize_rc(rc, &mut buf).unwrap();
+        }
+        writer.serialize_arc(&c, &mut buf).unwrap();
+        writer.serialize_arc(&c, &mut buf).unwrap();
+        // "shared" and "other" are encoded once each, plus five tags and 7u64 once.
+        assert_eq!(buf.len(), (8 + 6) + (8 + 5) + 5 * 8 + 8);
+
+        let mut from = &buf[..];
+        let mut reader = SharedReader::new();
+        let x = reader.deserialize_rc::<String>(&mut from).unwrap();
+        let y = reader.deserialize_rc::<String>(&mut from).unwrap();
+        let z = reader.deserialize_rc::<String>(&mut from).unwrap();
+        assert!(Rc::ptr_eq(&x, &z) && !Rc::ptr_eq(&x, &y));
+        assert_eq!(*y, "other");
+        let p = reader.deserialize_arc::<u64>(&mut from).unwrap();
+        let q = reader.deserialize_arc::<u64>(&mut from).unwrap();
+        assert!(Arc::ptr_eq(&p, &q));
+        assert!(reader.deserialize_arc::<u64>(&mut &[1u8, 0, 0, 0, 0, 0, 0, 0][..]).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+#[cfg(target_os = "linux")]
+use Advice;
+use OpenRandomAccessFile;
+use RafError;
+use RafMetadata;
+use RafOptions;
+use RandomAccessFile;
+use SharedRandomAccessFile;
+
+#[derive(Debug)]
+struct Shared {
+    file: File,
+    // Held by every operation that changes the file.
+    writes: Mutex<()>,
+}
+
+/// A cloneable handle to a `File` that many threads can use at once without wrapping it in a
+/// `Mutex`. Reads are positioned reads (`pread` on Unix) that run concurrently with each other
+/// and with writes; writes, appends and length changes are serialized across all clones, so
+/// that, for example, two appends never land at the same offset. Every clone shares the one file
+/// descriptor.
+///
+/// Besides `RandomAccessFile`, which needs a `&mut` handle and so one clone per thread, it
+/// implements `SharedRandomAccessFile`, so a single handle can also be shared by reference.
+#[derive(Debug, Clone)]
+pub struct SharedRaf {
+    inner: Arc<Shared>,
+}
+
+impl SharedRaf {
+    pub fn new(file: File) -> SharedRaf {
+        SharedRaf { inner: Arc::new(Shared { file, writes: Mutex::new(()) }) }
+    }
+
+    pub fn get_ref(&self) -> &File {
+        &self.inner.file
+    }
+
+    // A panic in another thread can't leave the file in a state the lock protects, so a poisoned
+    // lock is still good to use.
+    fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.inner.writes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// `lock::compare_and_write` for a file that is only borrowed.
+fn compare_and_write(file: &File, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+    if expected.len() != new.len() {
+        return Err(Error::new(ErrorKind::InvalidInput, "expected and new values differ in length").into());
+    }
+    let mut current = vec![0u8; expected.len()];
+    match file.read_exact_at_shared(at, &mut current) {
+        Ok(()) => (),
+        Err(RafError::ShortRead { .. }) => return Ok(false),
+        Err(e) => return Err(e)
+    }
+    if current != expected {
+        return Ok(false);
+    }
+    file.write_all_at_shared(at, new)?;
+    Ok(true)
+}
+
+impl OpenRandomAccessFile for SharedRaf {
+    fn open_with<P: AsRef<Path>>(path: P, options: &RafOptions) -> Result<SharedRaf, RafError> {
+        Ok(SharedRaf::new(options.open(path)?))
+    }
+}
+
+impl SharedRandomAccessFile for SharedRaf {
+    fn read_at_shared(&self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.inner.file.read_at_shared(at, dat)
+    }
+
+    fn write_at_shared(&self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let _writes = self.lock_writes();
+        self.inner.file.write_at_shared(at, data)
+    }
+}
+
+impl RandomAccessFile for SharedRaf {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.read_at_shared(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.write_at_shared(at, data)
+    }
+
+    /// Holds the write lock from finding the end of the file until the data is written.
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let _writes = self.lock_writes();
+        let at = File::metadata(&self.inner.file)?.len();
+        self.inner.file.write_all_at_shared(at, data)?;
+        Ok(at)
+    }
+
+    /// Clones share a file descriptor, and with it any `flock`, so the write lock is what keeps
+    /// them apart; the file lock is taken as well to keep out other handles.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        let _writes = self.lock_writes();
+        let file = &self.inner.file;
+        file.lock()?;
+        let result = compare_and_write(file, at, expected, new);
+        file.unlock()?;
+        result
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        Ok(File::metadata(&self.inner.file)?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        let _writes = self.lock_writes();
+        self.inner.file.set_len(len).map_err(RafError::from)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        Ok(RafMetadata::from(File::metadata(&self.inner.file)?))
+    }
+
+    /// Returns another handle to the same file; no descriptor is duplicated.
+    fn try_clone(&self) -> Result<SharedRaf, RafError> {
+        Ok(self.clone())
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        Some(&self.inner.file)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        let _writes = self.lock_writes();
+        ::backend::std_file::allocate(&self.inner.file, len)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        ::backend::std_file::fadvise(&self.inner.file, at, len, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        let _writes = self.lock_writes();
+        ::backend::std_file::punch_hole(&self.inner.file, at, len)
+    }
+
+    /// Does nothing: writes go straight to the file.
+    fn flush(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.file.sync_data().map_err(RafError::from)
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.file.sync_all().map_err(RafError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared_raf::SharedRaf;
+    use OpenRandomAccessFile;
+    use RandomAccessFile;
+    use SharedRandomAccessFile;
+    use std::env;
+    use std::fs;
+    use std::thread;
+
+    #[test]
+    fn clones_read_concurrently_and_serialize_appends() {
+        let path = env::temp_dir().join("raf_shared_raf.bin");
+        let _ = fs::remove_file(&path);
+        let raf: SharedRaf = OpenRandomAccessFile::new(&path).unwrap();
+        raf.write_all_at_shared(0, &[0xab; 16]).unwrap();
+
+        let workers: Vec<_> = (0..4u8).map(|id| {
+            let mut raf = raf.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    raf.append(&[id; 8]).unwrap();
+                    let mut header = [0u8; 16];
+                    raf.read_exact_at(0, &mut header).unwrap();
+                    assert_eq!(header, [0xab; 16]);
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let mut raf = raf;
+        assert_eq!(raf.len().unwrap(), 16 + 100 * 8);
+        let mut record = [0u8; 8];
+        for i in 0..100 {
+            raf.read_exact_at_shared(16 + 8 * i, &mut record).unwrap();
+            assert!(record.iter().all(|&b| b == record[0] && b < 4));
+        }
+        assert!(raf.compare_and_write_at(0, &[0xab; 2], b"ok").unwrap());
+        assert_eq!(raf.try_clone().unwrap().at(1).unwrap(), b'k');
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,173 @@
+use std::fs::File;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+/// Counters kept by a `StatsRaf`. Only calls that succeed are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Calls to `read_at`, including the ones the provided methods like `read_exact_at` make.
+    pub reads: u64,
+    /// Calls to `write_at`, including the ones the provided methods like `write_all_at` make.
+    pub writes: u64,
+    pub appends: u64,
+    /// Reads and writes that didn't start where the previous one ended, which a backend with a
+    /// cursor, or a spinning disk, pays a seek for.
+    pub seeks: u64,
+    /// Reads that returned fewer bytes than were asked for.
+    pub short_reads: u64,
+}
+
+/// A `RandomAccessFile` that counts the I/O going through it, to find out which part of a
+/// program is generating it. Counting is a few additions per call.
+#[derive(Debug)]
+pub struct StatsRaf<R: RandomAccessFile> {
+    inner: R,
+    stats: IoStats,
+    // Where the last read or write ended, if there was one.
+    position: Option<u64>,
+}
+
+impl<R: RandomAccessFile> StatsRaf<R> {
+    pub fn new(inner: R) -> StatsRaf<R> {
+        StatsRaf { inner, stats: IoStats::default(), position: None }
+    }
+
+    /// The counts since the handle was created or last reset.
+    pub fn stats(&self) -> IoStats {
+        self.stats
+    }
+
+    /// Sets every count back to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = IoStats::default();
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn moved_to(&mut self, at: u64, len: usize) {
+        if self.position.is_some_and(|position| position != at) {
+            self.stats.seeks += 1;
+        }
+        self.position = Some(at + len as u64);
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for StatsRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let n = self.inner.read_at(at, dat)?;
+        self.stats.reads += 1;
+        self.stats.bytes_read += n as u64;
+        if n < dat.len() {
+            self.stats.short_reads += 1;
+        }
+        self.moved_to(at, n);
+        Ok(n)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let n = self.inner.write_at(at, data)?;
+        self.stats.writes += 1;
+        self.stats.bytes_written += n as u64;
+        self.moved_to(at, n);
+        Ok(n)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let at = self.inner.append(data)?;
+        self.stats.appends += 1;
+        self.stats.bytes_written += data.len() as u64;
+        self.moved_to(at, data.len());
+        Ok(at)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.inner.sync_all()
+    }
+
+    /// Not counted as a read or a write.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stats::{IoStats, StatsRaf};
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+
+    #[test]
+    fn counts_transfers() {
+        let mut raf = StatsRaf::new(MemoryRandomAccessFile::new());
+        raf.append(b"0123").unwrap();
+        raf.write_all_at(4, b"4567").unwrap();
+        let mut buf = [0u8; 4];
+        raf.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(raf.read_at(6, &mut buf).unwrap(), 2);
+        assert_eq!(raf.stats(), IoStats {
+            bytes_read: 6,
+            bytes_written: 8,
+            reads: 2,
+            writes: 1,
+            appends: 1,
+            seeks: 2,
+            short_reads: 1,
+        });
+
+        raf.reset_stats();
+        assert_eq!(raf.stats(), IoStats::default());
+        raf.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(raf.stats().seeks, 1);
+        assert!(raf.compare_and_write_at(0, b"0123", b"abcd").unwrap());
+    }
+}
@@ -0,0 +1,67 @@
+//! A test double shared by the tests of the wrappers.
+
+use MemoryRandomAccessFile;
+use RafError;
+use RandomAccessFile;
+
+type Hook = Box<dyn FnMut(u64) -> Result<(), RafError> + Send>;
+
+// A `MemoryRandomAccessFile` that counts the calls reaching it and can run a hook before every
+// read, to stall or fail it.
+pub struct Counting {
+    pub inner: MemoryRandomAccessFile,
+    pub reads: usize,
+    // Calls to `write_at`, `append` and `compare_and_write_at`.
+    pub writes: usize,
+    before_read: Option<Hook>,
+}
+
+impl Counting {
+    pub fn new(inner: MemoryRandomAccessFile) -> Counting {
+        Counting { inner, reads: 0, writes: 0, before_read: None }
+    }
+
+    // Calls `hook` with the offset of every read before it is made; an error fails the read.
+    pub fn before_read<F>(mut self, hook: F) -> Counting
+        where F: FnMut(u64) -> Result<(), RafError> + Send + 'static {
+        self.before_read = Some(Box::new(hook));
+        self
+    }
+}
+
+impl RandomAccessFile for Counting {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.reads += 1;
+        if let Some(ref mut hook) = self.before_read {
+            hook(at)?;
+        }
+        self.inner.read_at(at, dat)
+    }
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.writes += 1;
+        self.inner.write_at(at, data)
+    }
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.writes += 1;
+        self.inner.append(data)
+    }
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.writes += 1;
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+    fn flush(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+#[derive(Debug)]
+struct Bucket {
+    // Tokens per second, which is also how many the bucket holds when full.
+    rate: f64,
+    // Negative while operations that have already been let through are being paid off.
+    tokens: f64,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Bucket {
+        assert!(rate != 0, "a rate limit must be non-zero");
+        Bucket { rate: rate as f64, tokens: rate as f64 }
+    }
+
+    fn refill(&mut self, elapsed: f64) {
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+    }
+
+    // Takes `n` tokens, returning how long the caller has to wait to have paid for them.
+    fn take(&mut self, n: f64) -> f64 {
+        self.tokens -= n;
+        if self.tokens < 0.0 { -self.tokens / self.rate } else { 0.0 }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    bytes: Option<Bucket>,
+    ops: Option<Bucket>,
+    last: Instant,
+}
+
+/// A token bucket limit on bytes and operations per second, which any number of `ThrottledRaf`
+/// handles can share by cloning it. Each bucket holds one second's worth of tokens, so after a
+/// quiet period up to a second's worth of traffic goes through at once.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// A limit of `bytes_per_sec` bytes and `ops_per_sec` operations per second; `None` leaves
+    /// that dimension unlimited. Panics if either is `Some(0)`.
+    pub fn new(bytes_per_sec: Option<u64>, ops_per_sec: Option<u64>) -> RateLimiter {
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(Buckets {
+                bytes: bytes_per_sec.map(Bucket::new),
+                ops: ops_per_sec.map(Bucket::new),
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until an operation transferring `bytes` bytes is allowed.
+    pub fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let elapsed = now.duration_since(buckets.last).as_secs_f64();
+            buckets.last = now;
+            let byte_wait = buckets.bytes.as_mut().map_or(0.0, |bucket| {
+                bucket.refill(elapsed);
+                bucket.take(bytes as f64)
+            });
+            let op_wait = buckets.ops.as_mut().map_or(0.0, |bucket| {
+                bucket.refill(elapsed);
+                bucket.take(1.0)
+            });
+            byte_wait.max(op_wait)
+        };
+        // The tokens are already taken, so other handles queue up behind this one while it sleeps.
+        if wait > 0.0 {
+            thread::sleep(Duration::from_secs_f64(wait));
+        }
+    }
+}
+
+/// A `RandomAccessFile` whose reads, writes, appends and syncs wait for a `RateLimiter`, so that
+/// background work like compaction or backups can't starve other I/O. A transfer is charged for
+/// the length asked for, before it happens; syncs count as operations of no bytes. Everything
+/// else goes through unthrottled.
+#[derive(Debug)]
+pub struct ThrottledRaf<R: RandomAccessFile> {
+    inner: R,
+    limiter: RateLimiter,
+}
+
+impl<R: RandomAccessFile> ThrottledRaf<R> {
+    pub fn new(inner: R, limiter: RateLimiter) -> ThrottledRaf<R> {
+        ThrottledRaf { inner, limiter }
+    }
+
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: RandomAccessFile> RandomAccessFile for ThrottledRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        self.limiter.acquire(dat.len());
+        self.inner.read_at(at, dat)
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        self.limiter.acquire(data.len());
+        self.inner.write_at(at, data)
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        self.limiter.acquire(data.len());
+        self.inner.append(data)
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.inner.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.set_len(len)
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.inner.flush()
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.limiter.acquire(0);
+        self.inner.sync_data()
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.limiter.acquire(0);
+        self.inner.sync_all()
+    }
+
+    /// Charged as one operation transferring `new`.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        self.limiter.acquire(new.len());
+        self.inner.compare_and_write_at(at, expected, new)
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.inner.metadata()
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        self.inner.as_file()
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.inner.allocate(len)
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.inner.advise(at, len, advice)
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.inner.punch_hole(at, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use throttle::{RateLimiter, ThrottledRaf};
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn shared_limit_delays_transfers() {
+        let limiter = RateLimiter::new(Some(1_000_000), None);
+        let mut a = ThrottledRaf::new(MemoryRandomAccessFile::new(), limiter.clone());
+        let mut b = ThrottledRaf::new(MemoryRandomAccessFile::new(), limiter);
+        let chunk = vec![7u8; 100_000];
+
+        // The first second's worth goes through at once, the rest at the limit.
+        let start = Instant::now();
+        for _ in 0..6 {
+            a.append(&chunk).unwrap();
+            b.append(&chunk).unwrap();
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert_eq!(a.len().unwrap() + b.len().unwrap(), 1_200_000);
+        assert!(a.compare_and_write_at(0, &[7; 4], &[8; 4]).unwrap());
+
+        let mut ops = ThrottledRaf::new(MemoryRandomAccessFile::new(), RateLimiter::new(None, Some(100)));
+        let start = Instant::now();
+        for _ in 0..110 {
+            ops.sync_data().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+}
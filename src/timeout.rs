@@ -0,0 +1,180 @@
+use std::fmt;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use Advice;
+use RafError;
+use RafMetadata;
+use RandomAccessFile;
+
+type Job<R> = Box<dyn FnOnce(&mut R) + Send>;
+
+/// A `RandomAccessFile` that gives up on a call that takes longer than `timeout`, returning an
+/// `ErrorKind::TimedOut` error instead of hanging forever, as calls on a dead network file system
+/// can. The inner file lives on a helper thread, which performs every call; reads and writes are
+/// copied to and from it, since the caller's buffers can't be lent to a call that may outlive the
+/// wait.
+///
+/// A call that timed out is not cancelled: it keeps the helper thread busy until it returns, and
+/// a write may still happen after its caller saw the error. Calls made in the meantime wait their
+/// turn, and time out too if it doesn't come soon enough.
+pub struct TimeoutRaf<R: RandomAccessFile + Send + 'static> {
+    jobs: Sender<Job<R>>,
+    timeout: Duration,
+}
+
+impl<R: RandomAccessFile + Send + 'static> fmt::Debug for TimeoutRaf<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutRaf").field("timeout", &self.timeout).finish()
+    }
+}
+
+impl<R: RandomAccessFile + Send + 'static> TimeoutRaf<R> {
+    pub fn new(inner: R, timeout: Duration) -> Result<TimeoutRaf<R>, RafError> {
+        let (jobs, queue) = mpsc::channel::<Job<R>>();
+        thread::Builder::new().name("raf-timeout".to_string()).spawn(move || {
+            let mut inner = inner;
+            for job in queue {
+                job(&mut inner);
+            }
+        })?;
+        Ok(TimeoutRaf { jobs, timeout })
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn call<T, F>(&mut self, op: F) -> Result<T, RafError>
+        where T: Send + 'static, F: FnOnce(&mut R) -> Result<T, RafError> + Send + 'static {
+        let (done, result) = mpsc::sync_channel(1);
+        let job: Job<R> = Box::new(move |inner| {
+            let _ = done.send(op(inner));
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(stopped());
+        }
+        match result.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(Error::new(ErrorKind::TimedOut, "the operation timed out").into()),
+            Err(RecvTimeoutError::Disconnected) => Err(stopped())
+        }
+    }
+}
+
+// The helper thread is only gone if an operation panicked on it.
+fn stopped() -> RafError {
+    Error::other("the helper thread has stopped").into()
+}
+
+impl<R: RandomAccessFile + Send + 'static> RandomAccessFile for TimeoutRaf<R> {
+    fn read_at(&mut self, at: u64, dat: &mut [u8]) -> Result<usize, RafError> {
+        let len = dat.len();
+        let buf = self.call(move |inner| {
+            let mut buf = vec![0u8; len];
+            let n = inner.read_at(at, &mut buf)?;
+            buf.truncate(n);
+            Ok(buf)
+        })?;
+        dat[..buf.len()].copy_from_slice(&buf);
+        Ok(buf.len())
+    }
+
+    fn write_at(&mut self, at: u64, data: &[u8]) -> Result<usize, RafError> {
+        let data = data.to_vec();
+        self.call(move |inner| inner.write_at(at, &data))
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<u64, RafError> {
+        let data = data.to_vec();
+        self.call(move |inner| inner.append(&data))
+    }
+
+    fn len(&mut self) -> Result<u64, RafError> {
+        self.call(|inner| inner.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), RafError> {
+        self.call(move |inner| inner.set_len(len))
+    }
+
+    fn flush(&mut self) -> Result<(), RafError> {
+        self.call(|inner| inner.flush())
+    }
+
+    fn sync_data(&mut self) -> Result<(), RafError> {
+        self.call(|inner| inner.sync_data())
+    }
+
+    fn sync_all(&mut self) -> Result<(), RafError> {
+        self.call(|inner| inner.sync_all())
+    }
+
+    /// Runs as a single call, so nothing else can get in between the comparison and the write.
+    fn compare_and_write_at(&mut self, at: u64, expected: &[u8], new: &[u8]) -> Result<bool, RafError> {
+        let (expected, new) = (expected.to_vec(), new.to_vec());
+        self.call(move |inner| inner.compare_and_write_at(at, &expected, &new))
+    }
+
+    fn metadata(&mut self) -> Result<RafMetadata, RafError> {
+        self.call(|inner| inner.metadata())
+    }
+
+    fn allocate(&mut self, len: u64) -> Result<(), RafError> {
+        self.call(move |inner| inner.allocate(len))
+    }
+
+    fn advise(&mut self, at: u64, len: u64, advice: Advice) -> Result<(), RafError> {
+        self.call(move |inner| inner.advise(at, len, advice))
+    }
+
+    fn punch_hole(&mut self, at: u64, len: u64) -> Result<(), RafError> {
+        self.call(move |inner| inner.punch_hole(at, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::Counting;
+    use timeout::TimeoutRaf;
+    use MemoryRandomAccessFile;
+    use RandomAccessFile;
+    use std::io::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn hung_calls_time_out() {
+        // Hangs for a while on reads at offset 99.
+        let inner = Counting::new(MemoryRandomAccessFile::from_vec(b"deadline".to_vec())).before_read(|at| {
+            if at == 99 {
+                thread::sleep(Duration::from_millis(300));
+            }
+            Ok(())
+        });
+        let mut raf = TimeoutRaf::new(inner, Duration::from_millis(50)).unwrap();
+        assert_eq!(raf.append(b"!").unwrap(), 8);
+        let mut buf = [0u8; 16];
+        assert_eq!(raf.read_at(4, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"line!");
+
+        let start = Instant::now();
+        assert_eq!(raf.read_at(99, &mut buf).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_millis(250));
+        // Queued behind the hung read.
+        assert_eq!(raf.len().unwrap_err().kind(), ErrorKind::TimedOut);
+
+        raf.set_timeout(Duration::from_secs(5));
+        assert_eq!(raf.len().unwrap(), 9);
+        assert!(raf.compare_and_write_at(0, b"dead", b"live").unwrap());
+    }
+}